@@ -0,0 +1,220 @@
+use crate::{fastq::extract, sequence::Sequence, two_pass::Assignment, Disambiseq};
+use hashbrown::HashMap;
+
+/// The location of an anchor within a read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnchorMatch {
+    /// The start of the anchor in the read
+    pub position: usize,
+    /// The number of mismatches between the read and the anchor
+    pub mismatches: usize,
+}
+
+/// The outcome of an anchor-guided barcode extraction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnchorOutcome {
+    /// The anchor was not found within the mismatch tolerance
+    MissingAnchor,
+    /// The anchor was found but the barcode falls outside of the read
+    Truncated { anchor: AnchorMatch },
+    /// The barcode was extracted and assigned against the index
+    Extracted {
+        anchor: AnchorMatch,
+        barcode: String,
+        assignment: Assignment,
+    },
+}
+impl AnchorOutcome {
+    /// Returns the assignment of an extracted barcode
+    pub fn assignment(&self) -> Option<&Assignment> {
+        match self {
+            Self::Extracted { assignment, .. } => Some(assignment),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts barcodes at a fixed offset from a constant flanking sequence,
+/// e.g. the guide of `CACCG[guide]GTTTT`.
+///
+/// The anchor is located by matching the windows of a read against every
+/// sequence within the mismatch tolerance of the anchor, built with the
+/// same point mutations as the alias libraries.
+#[derive(Debug, Clone)]
+pub struct AnchorExtractor {
+    anchor: String,
+    /// Every accepted anchor variant and its number of mismatches
+    neighbourhood: HashMap<String, usize>,
+    /// The start of the barcode relative to the start of the anchor
+    offset: isize,
+    length: usize,
+}
+impl AnchorExtractor {
+    /// Creates an extractor for a barcode of `length` bases directly
+    /// following an exact anchor
+    pub fn new(anchor: &str, length: usize) -> Self {
+        let anchor = anchor.to_ascii_uppercase();
+        let mut neighbourhood = HashMap::new();
+        neighbourhood.insert(anchor.clone(), 0);
+        Self {
+            offset: anchor.len() as isize,
+            anchor,
+            neighbourhood,
+            length,
+        }
+    }
+
+    /// Accepts anchors with up to `mismatches` substitutions
+    pub fn with_mismatches(mut self, mismatches: usize) -> Self {
+        let mut frontier = vec![self.anchor.clone()];
+        for distance in 1..=mismatches {
+            frontier = frontier
+                .iter()
+                .flat_map(|seq| Sequence::new(seq).mutate_all())
+                .filter(|variant| !self.neighbourhood.contains_key(variant))
+                .collect();
+            frontier.sort_unstable();
+            frontier.dedup();
+            frontier.iter().for_each(|variant| {
+                self.neighbourhood.insert(variant.clone(), distance);
+            });
+        }
+        self
+    }
+
+    /// Sets the start of the barcode relative to the start of the anchor.
+    /// Negative offsets place the barcode upstream of the anchor.
+    pub fn with_offset(mut self, offset: isize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Places the barcode directly upstream of the anchor
+    pub fn upstream(self) -> Self {
+        let offset = -(self.length as isize);
+        self.with_offset(offset)
+    }
+
+    pub fn anchor(&self) -> &str {
+        &self.anchor
+    }
+
+    /// Locates the anchor with the fewest mismatches in a read, preferring
+    /// the earliest position among equally good matches
+    pub fn find_anchor(&self, read: &[u8]) -> Option<AnchorMatch> {
+        let length = self.anchor.len();
+        let mut best: Option<AnchorMatch> = None;
+        for position in 0..(read.len() + 1).saturating_sub(length) {
+            let Some(window) = extract(read, position, length) else {
+                continue;
+            };
+            if let Some(&mismatches) = self.neighbourhood.get(window) {
+                if best.is_none_or(|b| mismatches < b.mismatches) {
+                    best = Some(AnchorMatch {
+                        position,
+                        mismatches,
+                    });
+                    if mismatches == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Extracts the barcode of a read, returning the anchor match and the
+    /// barcode if both are present
+    pub fn extract<'r>(&self, read: &'r [u8]) -> Option<(AnchorMatch, Option<&'r str>)> {
+        let anchor = self.find_anchor(read)?;
+        let start = anchor.position as isize + self.offset;
+        let barcode = usize::try_from(start)
+            .ok()
+            .and_then(|start| extract(read, start, self.length));
+        Some((anchor, barcode))
+    }
+
+    /// Extracts the barcode of a read and assigns it against an index
+    pub fn assign<T>(&self, read: &[u8], index: &Disambiseq<T>) -> AnchorOutcome {
+        match self.extract(read) {
+            None => AnchorOutcome::MissingAnchor,
+            Some((anchor, None)) => AnchorOutcome::Truncated { anchor },
+            Some((anchor, Some(barcode))) => AnchorOutcome::Extracted {
+                anchor,
+                barcode: barcode.to_string(),
+                assignment: index.assign(barcode),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn library() -> Disambiseq {
+        Disambiseq::from_slice(&["ACTGACTG".to_string(), "GGGGAAAA".to_string()])
+    }
+
+    #[test]
+    fn downstream() {
+        let dsq = library();
+        let extractor = AnchorExtractor::new("CACCG", 8);
+        match extractor.assign(b"TTTCACCGACTGACTGGTTTT", &dsq) {
+            AnchorOutcome::Extracted {
+                anchor,
+                barcode,
+                assignment,
+            } => {
+                assert_eq!(anchor.position, 3);
+                assert_eq!(anchor.mismatches, 0);
+                assert_eq!(barcode, "ACTGACTG");
+                assert!(matches!(assignment, Assignment::Exact(_)));
+            }
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+        assert_eq!(
+            extractor.assign(b"TTTCAACGACTGACTGGTTTT", &dsq),
+            AnchorOutcome::MissingAnchor
+        );
+        assert!(matches!(
+            extractor.assign(b"TTTCACCGACTG", &dsq),
+            AnchorOutcome::Truncated { .. }
+        ));
+    }
+
+    #[test]
+    fn mismatches() {
+        let dsq = library();
+        let extractor = AnchorExtractor::new("CACCG", 8).with_mismatches(1);
+        let outcome = extractor.assign(b"TTCAACGGGGGAAAT", &dsq);
+        match &outcome {
+            AnchorOutcome::Extracted { anchor, .. } => assert_eq!(anchor.mismatches, 1),
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+        assert_eq!(
+            outcome.assignment().unwrap().parent().unwrap().sequence(),
+            "GGGGAAAA"
+        );
+
+        // an exact anchor is preferred over an earlier approximate one
+        let anchor = extractor.find_anchor(b"CAACGNNCACCG").unwrap();
+        assert_eq!(anchor.position, 7);
+        assert_eq!(anchor.mismatches, 0);
+
+        let extractor = AnchorExtractor::new("CACCG", 8).with_mismatches(2);
+        assert_eq!(extractor.find_anchor(b"CTACG").unwrap().mismatches, 2);
+    }
+
+    #[test]
+    fn upstream() {
+        let dsq = library();
+        let extractor = AnchorExtractor::new("GTTTT", 8).upstream();
+        let outcome = extractor.assign(b"CACCGACTGACTGGTTTTAGAG", &dsq);
+        assert!(matches!(outcome.assignment(), Some(Assignment::Exact(_))));
+        assert!(matches!(
+            extractor.assign(b"CTGACTGGTTTT", &dsq),
+            AnchorOutcome::Truncated { .. }
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+use crate::{distance::DistanceMetric, error::Result, Disambiseq, SeqWrapper};
+use hashbrown::{HashMap, HashSet};
+use std::{borrow::Cow, io::Write};
+
+/// An edge between two parents sharing ambiguous aliases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollisionEdge {
+    pub a: SeqWrapper,
+    pub b: SeqWrapper,
+    /// The number of ambiguous aliases both parents generate
+    pub shared: usize,
+    /// The Hamming distance between the parents
+    pub distance: usize,
+}
+
+/// The parent–parent collision graph of a library: parents are nodes and
+/// two parents are connected whenever they share an ambiguous alias
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollisionGraph {
+    /// Every parent sorted by sequence, including isolated ones
+    pub nodes: Vec<SeqWrapper>,
+    /// Edges sorted by their endpoints
+    pub edges: Vec<CollisionEdge>,
+}
+
+/// Parents to keep and drop so that the remaining library is free of
+/// collisions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pruning {
+    /// Parents that keep their full one-mismatch neighbourhood once the
+    /// dropped parents are removed
+    pub retained: Vec<SeqWrapper>,
+    /// Parents to drop or re-synthesize, in the order they were chosen
+    pub dropped: Vec<SeqWrapper>,
+}
+
+impl CollisionGraph {
+    /// Returns the number of edges of each parent
+    pub fn degrees(&self) -> HashMap<&SeqWrapper, usize> {
+        let mut degrees: HashMap<&SeqWrapper, usize> = self.nodes.iter().map(|n| (n, 0)).collect();
+        for edge in &self.edges {
+            *degrees.entry(&edge.a).or_default() += 1;
+            *degrees.entry(&edge.b).or_default() += 1;
+        }
+        degrees
+    }
+
+    /// Suggests a small set of parents to drop so that no edges remain.
+    ///
+    /// The parent with the most remaining collisions is dropped first,
+    /// breaking ties by the number of shared aliases and then by
+    /// sequence. This is a greedy approximation of the minimum vertex
+    /// cover.
+    pub fn prune(&self) -> Pruning {
+        let mut edges: Vec<&CollisionEdge> = self.edges.iter().collect();
+        let mut dropped: Vec<SeqWrapper> = Vec::new();
+        while !edges.is_empty() {
+            let mut load: HashMap<&SeqWrapper, (usize, usize)> = HashMap::new();
+            for edge in &edges {
+                for node in [&edge.a, &edge.b] {
+                    let entry = load.entry(node).or_default();
+                    entry.0 += 1;
+                    entry.1 += edge.shared;
+                }
+            }
+            let Some((worst, _)) = load
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.sequence().cmp(a.0.sequence())))
+            else {
+                break;
+            };
+            let worst = worst.clone();
+            edges.retain(|e| e.a != worst && e.b != worst);
+            dropped.push(worst);
+        }
+        let retained = {
+            let removed: HashSet<&SeqWrapper> = dropped.iter().collect();
+            self.nodes
+                .iter()
+                .filter(|n| !removed.contains(n))
+                .cloned()
+                .collect()
+        };
+        Pruning { retained, dropped }
+    }
+
+    /// Writes the graph in Graphviz DOT format. Edges are labelled and
+    /// weighted by their number of shared aliases.
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "graph collisions {{")?;
+        for node in &self.nodes {
+            writeln!(writer, "  \"{}\";", node.sequence())?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                writer,
+                "  \"{}\" -- \"{}\" [weight={}, label=\"{}\", distance={}];",
+                edge.a.sequence(),
+                edge.b.sequence(),
+                edge.shared,
+                edge.shared,
+                edge.distance
+            )?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    /// Writes the graph in GraphML format with the `shared` and
+    /// `distance` edge attributes
+    pub fn write_graphml<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="shared" for="edge" attr.name="shared" attr.type="int"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="distance" for="edge" attr.name="distance" attr.type="int"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <graph id="collisions" edgedefault="undirected">"#
+        )?;
+        for node in &self.nodes {
+            writeln!(
+                writer,
+                r#"    <node id="{}"/>"#,
+                xml_escape(node.sequence())
+            )?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                writer,
+                r#"    <edge source="{}" target="{}">"#,
+                xml_escape(edge.a.sequence()),
+                xml_escape(edge.b.sequence())
+            )?;
+            writeln!(writer, r#"      <data key="shared">{}</data>"#, edge.shared)?;
+            writeln!(
+                writer,
+                r#"      <data key="distance">{}</data>"#,
+                edge.distance
+            )?;
+            writeln!(writer, "    </edge>")?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        Ok(())
+    }
+}
+
+/// Escapes the characters that may not appear verbatim in an XML attribute
+fn xml_escape(value: &str) -> Cow<'_, str> {
+    if !value.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+impl<T> Disambiseq<T> {
+    /// Builds the collision graph from the candidate parents recorded for
+    /// every ambiguous alias
+    pub fn collision_graph(&self) -> CollisionGraph {
+        let mut shared: HashMap<(SeqWrapper, SeqWrapper), usize> = HashMap::new();
+        for alias in self.ambiguous() {
+            let Some(candidates) = self.candidates(alias.sequence()) else {
+                continue;
+            };
+            for (i, a) in candidates.iter().enumerate() {
+                for b in &candidates[i + 1..] {
+                    let key = if a.sequence() <= b.sequence() {
+                        (a.clone(), b.clone())
+                    } else {
+                        (b.clone(), a.clone())
+                    };
+                    *shared.entry(key).or_default() += 1;
+                }
+            }
+        }
+        let mut edges: Vec<CollisionEdge> = shared
+            .into_iter()
+            .map(|((a, b), shared)| {
+                let distance = DistanceMetric::Hamming
+                    .distance(a.sequence().as_bytes(), b.sequence().as_bytes());
+                CollisionEdge {
+                    a,
+                    b,
+                    shared,
+                    distance,
+                }
+            })
+            .collect();
+        edges.sort_unstable_by(|x, y| {
+            (x.a.sequence(), x.b.sequence()).cmp(&(y.a.sequence(), y.b.sequence()))
+        });
+        let mut nodes: Vec<SeqWrapper> = self.parents().iter().cloned().collect();
+        nodes.sort_unstable_by(|a, b| a.sequence().cmp(b.sequence()));
+        CollisionGraph { nodes, edges }
+    }
+
+    /// Suggests the parents to drop (or re-synthesize) so that every
+    /// remaining parent keeps its full one-mismatch neighbourhood
+    pub fn suggest_pruning(&self) -> Pruning {
+        self.collision_graph().prune()
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::design::BarcodeDesigner;
+    use std::sync::Arc;
+
+    fn library() -> Disambiseq {
+        // two pairs of parents one mismatch apart and an isolated parent
+        let parents = ["AAAA", "AAAT", "CCCC", "CCCG", "GGGG"];
+        Disambiseq::from_slice(&parents.map(String::from))
+    }
+
+    #[test]
+    fn graph() {
+        let graph = library().collision_graph();
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[0].a.sequence(), "AAAA");
+        assert_eq!(graph.edges[0].b.sequence(), "AAAT");
+        assert_eq!(graph.edges[0].shared, 2);
+        assert_eq!(graph.edges[0].distance, 1);
+        assert_eq!(graph.degrees()[&graph.nodes[4]], 0);
+    }
+
+    #[test]
+    fn dot() {
+        let dsq = Disambiseq::from_slice(&["AAAA".to_string(), "AATT".to_string()]);
+        let mut out = Vec::new();
+        dsq.collision_graph().write_dot(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "graph collisions {\n  \"AAAA\";\n  \"AATT\";\n  \
+             \"AAAA\" -- \"AATT\" [weight=2, label=\"2\", distance=2];\n}\n"
+        );
+    }
+
+    #[test]
+    fn graphml() {
+        let mut out = Vec::new();
+        library().collision_graph().write_graphml(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("<?xml"));
+        assert_eq!(out.matches("<node ").count(), 5);
+        assert_eq!(out.matches("<edge ").count(), 2);
+        assert!(out.contains(
+            "<edge source=\"CCCC\" target=\"CCCG\">\n      \
+             <data key=\"shared\">2</data>\n      \
+             <data key=\"distance\">1</data>\n    </edge>"
+        ));
+        assert!(out.trim_end().ends_with("</graphml>"));
+    }
+
+    #[test]
+    fn graphml_escape() {
+        let a = SeqWrapper(Arc::new("A&B".to_string()));
+        let b = SeqWrapper(Arc::new("<\"C'>".to_string()));
+        let graph = CollisionGraph {
+            nodes: vec![a.clone(), b.clone()],
+            edges: vec![CollisionEdge {
+                a,
+                b,
+                shared: 1,
+                distance: 3,
+            }],
+        };
+        let mut out = Vec::new();
+        graph.write_graphml(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"<node id="A&amp;B"/>"#));
+        assert!(out.contains(r#"<node id="&lt;&quot;C&apos;&gt;"/>"#));
+        assert!(out.contains(r#"<edge source="A&amp;B" target="&lt;&quot;C&apos;&gt;">"#));
+    }
+
+    #[test]
+    fn prune() {
+        let mut dsq = Disambiseq::new();
+        for parent in ["AAAA", "AAAT", "AATA", "TAAA", "GGGG"] {
+            dsq.insert(parent);
+        }
+        let pruning = dsq.suggest_pruning();
+        let dropped: Vec<&str> = pruning.dropped.iter().map(|p| p.sequence()).collect();
+        assert_eq!(dropped, vec!["AAAA", "AAAT", "AATA"]);
+
+        let retained: Vec<String> = pruning
+            .retained
+            .iter()
+            .map(|p| p.sequence().to_string())
+            .collect();
+        let pruned = Disambiseq::from_slice(&retained);
+        assert!(pruned.ambiguous().is_empty());
+        assert!(pruned.report().is_correctable());
+
+        // replacements for the dropped parents keep the library clean
+        let replacements = BarcodeDesigner::new(pruning.dropped.len(), 4)
+            .with_reserved(&retained)
+            .design()
+            .unwrap();
+        let mut redesigned = pruned.clone();
+        replacements.iter().for_each(|r| redesigned.insert(r));
+        assert_eq!(redesigned.parents().len(), 5);
+        assert!(redesigned.ambiguous().is_empty());
+    }
+}
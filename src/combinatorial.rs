@@ -0,0 +1,274 @@
+use crate::{
+    error::{Error, Result},
+    fastq::extract,
+    two_pass::Assignment,
+    utils::hamming_distance,
+    Disambiseq,
+};
+
+/// A single element of a split-pool read layout
+#[derive(Debug, Clone)]
+enum Element {
+    /// A barcode round by its position in the rounds of the decoder
+    Round(usize),
+    /// A constant linker sequence between rounds
+    Linker(String),
+}
+
+/// The outcome of decoding a split-pool read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    /// Every round resolved; the parent id of each round in order
+    Cell(Vec<String>),
+    /// At least one round could not be resolved; the round number and
+    /// assignment (unassigned or ambiguous) of each failed round
+    Failed(Vec<(usize, Assignment)>),
+    /// A linker differs from its expected sequence by more than the
+    /// tolerated number of mismatches
+    LinkerMismatch { linker: usize, mismatches: usize },
+    /// The read is too short for the layout
+    Truncated,
+}
+
+/// Decodes SPLiT-seq / sci-seq style reads carrying several barcode
+/// rounds separated by linkers, each round with its own whitelist.
+///
+/// Elements are laid out in the order they are added starting at the
+/// offset of the decoder. Parent ids are the payloads of each round's
+/// index, or the parent sequences for rounds added from sequences.
+#[derive(Debug, Clone, Default)]
+pub struct CombinatorialDecoder {
+    offset: usize,
+    /// The whitelist of each round and the length of its barcodes
+    rounds: Vec<(Disambiseq<String>, usize)>,
+    elements: Vec<Element>,
+    linker_mismatches: Option<usize>,
+    separator: String,
+}
+impl CombinatorialDecoder {
+    /// Creates a decoder whose first element starts at `offset`
+    pub fn new(offset: usize) -> Self {
+        Self {
+            offset,
+            separator: "_".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Appends a barcode round whose parents are named by their payload.
+    /// The index must be non-empty and its parents must share one length.
+    pub fn round(mut self, index: Disambiseq<String>) -> Result<Self> {
+        let round = self.rounds.len();
+        let length = match index.lengths() {
+            [length] => *length,
+            [] => {
+                return Err(Error::InvalidRound {
+                    round,
+                    message: "no barcodes".to_string(),
+                })
+            }
+            lengths => {
+                return Err(Error::InvalidRound {
+                    round,
+                    message: format!("mixed barcode lengths {lengths:?}"),
+                })
+            }
+        };
+        self.elements.push(Element::Round(round));
+        self.rounds.push((index, length));
+        Ok(self)
+    }
+
+    /// Appends a barcode round whose parents are named by their sequence
+    pub fn round_from_sequences<S: AsRef<str>>(self, sequences: &[S]) -> Result<Self> {
+        let mut index = Disambiseq::named();
+        sequences
+            .iter()
+            .for_each(|s| index.insert_named(s.as_ref(), s.as_ref().to_string()));
+        self.round(index)
+    }
+
+    /// Appends a linker sequence
+    pub fn linker(mut self, linker: &str) -> Self {
+        self.elements.push(Element::Linker(linker.to_string()));
+        self
+    }
+
+    /// Verifies linkers allowing up to `mismatches` substitutions. By
+    /// default linkers are skipped without being checked.
+    pub fn with_linker_mismatches(mut self, mismatches: usize) -> Self {
+        self.linker_mismatches = Some(mismatches);
+        self
+    }
+
+    /// Sets the separator joining round ids into a cell id (default `_`)
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Returns the number of barcode rounds
+    pub fn num_rounds(&self) -> usize {
+        self.rounds.len()
+    }
+
+    /// Decodes the rounds of a read
+    pub fn decode(&self, read: &[u8]) -> Decoded {
+        let mut position = self.offset;
+        let mut ids = Vec::new();
+        let mut failed = Vec::new();
+        let mut linker = 0;
+        for element in &self.elements {
+            match element {
+                Element::Round(round) => {
+                    let (index, length) = &self.rounds[*round];
+                    let Some(barcode) = extract(read, position, *length) else {
+                        return Decoded::Truncated;
+                    };
+                    let assignment = index.assign(barcode);
+                    match assignment.parent() {
+                        Some(p) => ids.push(
+                            index
+                                .payload(p.sequence())
+                                .cloned()
+                                .unwrap_or_else(|| p.sequence().to_string()),
+                        ),
+                        None => failed.push((*round, assignment)),
+                    }
+                    position += length;
+                }
+                Element::Linker(expected) => {
+                    let Some(observed) = position
+                        .checked_add(expected.len())
+                        .and_then(|end| read.get(position..end))
+                    else {
+                        return Decoded::Truncated;
+                    };
+                    if let Some(tolerance) = self.linker_mismatches {
+                        let mismatches =
+                            hamming_distance(observed, expected.as_bytes()).unwrap_or(0);
+                        if mismatches > tolerance {
+                            return Decoded::LinkerMismatch { linker, mismatches };
+                        }
+                    }
+                    linker += 1;
+                    position += expected.len();
+                }
+            }
+        }
+        if failed.is_empty() {
+            Decoded::Cell(ids)
+        } else {
+            Decoded::Failed(failed)
+        }
+    }
+
+    /// Joins the round ids of a decoded cell into a single cell id
+    pub fn cell_id(&self, ids: &[String]) -> String {
+        ids.join(&self.separator)
+    }
+
+    /// Decodes a read into its combined cell id if every round resolved
+    pub fn decode_cell_id(&self, read: &[u8]) -> Option<String> {
+        match self.decode(read) {
+            Decoded::Cell(ids) => Some(self.cell_id(&ids)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn named(pairs: &[(&str, &str)]) -> Disambiseq<String> {
+        let mut dsq = Disambiseq::named();
+        pairs
+            .iter()
+            .for_each(|(seq, id)| dsq.insert_named(seq, id.to_string()));
+        dsq
+    }
+
+    fn decoder() -> CombinatorialDecoder {
+        CombinatorialDecoder::new(2)
+            .round(named(&[("AAAA", "A01"), ("CCCC", "A02")]))
+            .unwrap()
+            .linker("GTGT")
+            .round(named(&[("GGGG", "B01"), ("TTTT", "B02")]))
+            .unwrap()
+            .linker("CACA")
+            .round_from_sequences(&["ACAC", "ACAG"])
+            .unwrap()
+    }
+
+    #[test]
+    fn invalid_round() {
+        let decoder = CombinatorialDecoder::new(0);
+        assert!(matches!(
+            decoder.clone().round(Disambiseq::named()),
+            Err(Error::InvalidRound { round: 0, .. })
+        ));
+        assert!(matches!(
+            decoder
+                .round_from_sequences(&["AAAA"])
+                .unwrap()
+                .round_from_sequences(&["CCCC", "GGGGG"]),
+            Err(Error::InvalidRound { round: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn decode_cell() {
+        let decoder = decoder();
+        assert_eq!(decoder.num_rounds(), 3);
+        let read = b"NNAAAAGTGTTTTTCACAACACNN";
+        assert_eq!(
+            decoder.decode(read),
+            Decoded::Cell(vec!["A01".into(), "B02".into(), "ACAC".into()])
+        );
+        assert_eq!(decoder.decode_cell_id(read).unwrap(), "A01_B02_ACAC");
+
+        // corrected rounds and an unchecked linker
+        let read = b"NNCCCAGTGAAGGGCACATCAGNN";
+        assert_eq!(
+            decoder.with_separator("-").decode_cell_id(read).unwrap(),
+            "A02-B01-ACAG"
+        );
+    }
+
+    #[test]
+    fn decode_failed() {
+        let decoder = decoder();
+        match decoder.decode(b"NNAAAAGTGTGGTTCACAACATNN") {
+            Decoded::Failed(failed) => {
+                assert_eq!(failed.len(), 2);
+                assert_eq!(failed[0], (1, Assignment::Unassigned));
+                assert_eq!(failed[1], (2, Assignment::Ambiguous));
+            }
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+        assert_eq!(decoder.decode(b"NNAAAAGTGTTTTTCA"), Decoded::Truncated);
+        assert_eq!(
+            CombinatorialDecoder::new(usize::MAX)
+                .linker("GTGT")
+                .decode(b"GTGT"),
+            Decoded::Truncated
+        );
+    }
+
+    #[test]
+    fn decode_linker() {
+        let decoder = decoder().with_linker_mismatches(1);
+        assert!(matches!(
+            decoder.decode(b"NNAAAAGTGATTTTCACAACAC"),
+            Decoded::Cell(_)
+        ));
+        assert_eq!(
+            decoder.decode(b"NNAAAAGTGTTTTTCTCTACAC"),
+            Decoded::LinkerMismatch {
+                linker: 1,
+                mismatches: 2
+            }
+        );
+    }
+}
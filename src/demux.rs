@@ -0,0 +1,496 @@
+use crate::{
+    error::{Error, Result},
+    fastq::{extract, FastqReader, FastqRecord, MateReader},
+    read_structure::MultiReadStructure,
+    two_pass::Assignment,
+    Disambiseq, SeqWrapper,
+};
+use hashbrown::{HashMap, HashSet};
+use std::{
+    borrow::Borrow,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the output receiving reads without a matching parent
+pub const UNASSIGNED: &str = "unassigned";
+
+/// Name of the output receiving reads with an ambiguous parent
+pub const AMBIGUOUS: &str = "ambiguous";
+
+/// Default number of output files kept open at once
+pub const DEFAULT_MAX_OPEN_FILES: usize = 256;
+
+/// Counts of reads routed to each output of a demultiplexer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DemuxSummary {
+    /// Number of reads assigned to each named parent
+    pub counts: HashMap<String, usize>,
+    pub unassigned: usize,
+    pub ambiguous: usize,
+}
+impl DemuxSummary {
+    /// Returns the total number of reads processed
+    pub fn total(&self) -> usize {
+        self.counts.values().sum::<usize>() + self.unassigned + self.ambiguous
+    }
+
+    /// Writes the summary as TSV with the columns `name` and `count`,
+    /// sorted by name and followed by the unassigned and ambiguous counts
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_unstable();
+        writeln!(writer, "name\tcount")?;
+        for (name, count) in counts {
+            writeln!(writer, "{name}\t{count}")?;
+        }
+        writeln!(writer, "{UNASSIGNED}\t{}", self.unassigned)?;
+        writeln!(writer, "{AMBIGUOUS}\t{}", self.ambiguous)?;
+        Ok(())
+    }
+}
+
+/// Routes FASTQ records into one file per resolved parent.
+///
+/// The barcode is read at a fixed offset of the first mate. Output files
+/// are created lazily in the output directory as `<name>.fastq` for single
+/// reads or `<name>_R1.fastq` and `<name>_R2.fastq` for paired reads, and
+/// unassigned and ambiguous reads go to their own files.
+///
+/// Output names are resolved for every parent when the demultiplexer is
+/// built and must be plain file names distinct from the reserved
+/// [`UNASSIGNED`] and [`AMBIGUOUS`] outputs. Parents sharing a name are
+/// merged into the same output. At most [`DEFAULT_MAX_OPEN_FILES`] outputs
+/// are kept open at once: when the limit is reached all outputs are
+/// flushed and closed, and reopened for appending as reads arrive.
+///
+/// Reads spread across several mate files are written as
+/// `<name>_R<n>.fastq`, and a per-mate read structure may be used to
+/// locate the barcode across mates instead of the fixed offset.
+pub struct Demultiplexer<'a, T = ()> {
+    index: &'a Disambiseq<T>,
+    offset: usize,
+    length: usize,
+    structure: Option<MultiReadStructure>,
+    output_dir: PathBuf,
+    names: HashMap<SeqWrapper, String>,
+    max_open: usize,
+    created: HashSet<String>,
+    writers: HashMap<String, BufWriter<File>>,
+    summary: DemuxSummary,
+}
+impl<'a, T> Demultiplexer<'a, T> {
+    /// Creates a demultiplexer naming outputs after the parent sequences
+    pub fn new<P: AsRef<Path>>(
+        index: &'a Disambiseq<T>,
+        offset: usize,
+        length: usize,
+        output_dir: P,
+    ) -> Result<Self> {
+        Self {
+            index,
+            offset,
+            length,
+            structure: None,
+            output_dir: output_dir.as_ref().to_path_buf(),
+            names: HashMap::new(),
+            max_open: DEFAULT_MAX_OPEN_FILES,
+            created: HashSet::new(),
+            writers: HashMap::new(),
+            summary: DemuxSummary::default(),
+        }
+        .with_namer(|parent, _| parent.sequence().to_string())
+    }
+
+    /// Sets how outputs are named from a parent and its payload, failing
+    /// if any parent is given an invalid or reserved name
+    pub fn with_namer<F>(mut self, namer: F) -> Result<Self>
+    where
+        F: Fn(&SeqWrapper, Option<&T>) -> String,
+    {
+        let mut names = HashMap::with_capacity(self.index.parents().len());
+        for parent in self.index.parents() {
+            let name = namer(parent, self.index.payload(parent.sequence()));
+            validate_name(&name)?;
+            names.insert(parent.clone(), name);
+        }
+        self.names = names;
+        Ok(self)
+    }
+
+    /// Sets the number of output files kept open at once
+    pub fn with_max_open_files(mut self, max_open: usize) -> Self {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    /// Reads the barcode of multi-mate reads from the barcode segments of
+    /// a per-mate read structure, concatenated in order, instead of the
+    /// fixed offset of the first mate
+    pub fn with_structure(mut self, structure: MultiReadStructure) -> Self {
+        self.structure = Some(structure);
+        self
+    }
+
+    /// Returns the counts of reads routed so far
+    pub fn summary(&self) -> &DemuxSummary {
+        &self.summary
+    }
+
+    /// Resolves the output name of a read and records it in the summary
+    fn route(&mut self, assignment: &Assignment) -> String {
+        match assignment {
+            Assignment::Ambiguous => {
+                self.summary.ambiguous += 1;
+                AMBIGUOUS.to_string()
+            }
+            Assignment::Unassigned => {
+                self.summary.unassigned += 1;
+                UNASSIGNED.to_string()
+            }
+            Assignment::Exact(p) | Assignment::Corrected(p) | Assignment::Rescued(p) => {
+                let name = self.names[p].clone();
+                *self.summary.counts.entry_ref(&name).or_default() += 1;
+                name
+            }
+        }
+    }
+
+    fn assign(&self, record: &FastqRecord) -> Assignment {
+        match extract(&record.seq, self.offset, self.length) {
+            Some(barcode) => self.index.assign(barcode),
+            None => Assignment::Unassigned,
+        }
+    }
+
+    fn assign_mates<M: Borrow<FastqRecord>>(&self, mates: &[M]) -> Result<Assignment> {
+        let assignment = match &self.structure {
+            Some(structure) => match structure.extract(mates)? {
+                Some(extracted) => self.index.assign(&extracted.barcodes.concat()),
+                None => Assignment::Unassigned,
+            },
+            None => match mates.first() {
+                Some(record) => self.assign(record.borrow()),
+                None => Assignment::Unassigned,
+            },
+        };
+        Ok(assignment)
+    }
+
+    fn write(&mut self, file_name: String, record: &FastqRecord) -> Result<()> {
+        if !self.writers.contains_key(&file_name) {
+            if self.writers.len() >= self.max_open {
+                self.close_all()?;
+            }
+            let path = self.output_dir.join(&file_name);
+            // outputs closed earlier are reopened without truncation
+            let file = if self.created.contains(&file_name) {
+                OpenOptions::new().append(true).open(path)?
+            } else {
+                self.created.insert(file_name.clone());
+                File::create(path)?
+            };
+            self.writers.insert(file_name.clone(), BufWriter::new(file));
+        }
+        if let Some(writer) = self.writers.get_mut(&file_name) {
+            record.write(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Routes a single record to the output of its parent
+    pub fn process(&mut self, record: &FastqRecord) -> Result<Assignment> {
+        let assignment = self.assign(record);
+        let name = self.route(&assignment);
+        self.write(format!("{name}.fastq"), record)?;
+        Ok(assignment)
+    }
+
+    /// Routes a pair of records to the outputs of their parent, read as
+    /// in [`process_mates`](Self::process_mates)
+    pub fn process_paired(&mut self, r1: &FastqRecord, r2: &FastqRecord) -> Result<Assignment> {
+        self.process_mates(&[r1, r2])
+    }
+
+    /// Routes the records of every mate of a read to the outputs of its
+    /// parent. Fails if a read structure is set and the number of mates
+    /// differs from its number of structures.
+    pub fn process_mates(&mut self, mates: &[&FastqRecord]) -> Result<Assignment> {
+        self.route_mates(mates)
+    }
+
+    /// Routes owned or borrowed mates without collecting references
+    fn route_mates<M: Borrow<FastqRecord>>(&mut self, mates: &[M]) -> Result<Assignment> {
+        let assignment = self.assign_mates(mates)?;
+        let name = self.route(&assignment);
+        for (idx, mate) in mates.iter().enumerate() {
+            self.write(format!("{name}_R{}.fastq", idx + 1), mate.borrow())?;
+        }
+        Ok(assignment)
+    }
+
+    /// Demultiplexes every record of a FASTQ stream
+    pub fn run<R: BufRead>(&mut self, reader: R) -> Result<()> {
+        for record in FastqReader::new(reader) {
+            self.process(&record?)?;
+        }
+        Ok(())
+    }
+
+    /// Demultiplexes every pair of records of two FASTQ streams
+    pub fn run_paired<'r, R1, R2>(&mut self, r1: R1, r2: R2) -> Result<()>
+    where
+        R1: BufRead + 'r,
+        R2: BufRead + 'r,
+    {
+        let readers: Vec<Box<dyn BufRead + 'r>> = vec![Box::new(r1), Box::new(r2)];
+        self.run_mates(readers)
+    }
+
+    /// Demultiplexes synchronized mate files, e.g. R1, R2 and the index
+    /// reads
+    pub fn run_mates<R: BufRead>(&mut self, readers: Vec<R>) -> Result<()> {
+        if let Some(structure) = &self.structure {
+            structure.check_mates(readers.len())?;
+        }
+        for mates in MateReader::new(readers) {
+            self.route_mates(&mates?)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes every open output
+    fn close_all(&mut self) -> Result<()> {
+        for (_, mut writer) in self.writers.drain() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes all outputs and returns the summary
+    pub fn finish(mut self) -> Result<DemuxSummary> {
+        self.close_all()?;
+        Ok(self.summary)
+    }
+}
+
+/// Checks that an output name is a plain file name that does not collide
+/// with the reserved outputs
+fn validate_name(name: &str) -> Result<()> {
+    let message = if name.is_empty() {
+        "empty name"
+    } else if name == "." || name == ".." {
+        "relative path component"
+    } else if name.contains(['/', '\\', '\0']) {
+        "contains a path separator"
+    } else if name == UNASSIGNED || name == AMBIGUOUS {
+        "collides with a reserved output"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidOutputName {
+        name: name.to_string(),
+        message: message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::error::Error;
+    use std::fs;
+
+    const FASTQ: &str = "@r1\nACTGG\n+\nIIIII\n\
+                         @r2\nTCTGG\n+\nIIIII\n\
+                         @r3\nATTGG\n+\nIIIII\n\
+                         @r4\nGGGGG\n+\nIIIII\n\
+                         @r5\nAGTGG\n+\nIIIII\n";
+
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("disambiseq-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn demultiplex() {
+        let dir = output_dir("demux");
+        let mut dsq = Disambiseq::named();
+        dsq.insert_named("ACT", "sample1");
+        dsq.insert_named("AGT", "sample2");
+
+        let mut demux = Demultiplexer::new(&dsq, 0, 3, &dir)
+            .unwrap()
+            .with_namer(|p, name| name.map_or(p.sequence().to_string(), |n| n.to_string()))
+            .unwrap();
+        demux.run(FASTQ.as_bytes()).unwrap();
+        let summary = demux.finish().unwrap();
+
+        assert_eq!(summary.counts["sample1"], 2);
+        assert_eq!(summary.counts["sample2"], 1);
+        assert_eq!(summary.ambiguous, 1);
+        assert_eq!(summary.unassigned, 1);
+        assert_eq!(summary.total(), 5);
+
+        let sample1 = fs::read_to_string(dir.join("sample1.fastq")).unwrap();
+        assert_eq!(sample1, "@r1\nACTGG\n+\nIIIII\n@r2\nTCTGG\n+\nIIIII\n");
+        let ambiguous = fs::read_to_string(dir.join("ambiguous.fastq")).unwrap();
+        assert_eq!(ambiguous, "@r3\nATTGG\n+\nIIIII\n");
+        let unassigned = fs::read_to_string(dir.join("unassigned.fastq")).unwrap();
+        assert_eq!(unassigned, "@r4\nGGGGG\n+\nIIIII\n");
+
+        let mut out = Vec::new();
+        summary.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "name\tcount\nsample1\t2\nsample2\t1\nunassigned\t1\nambiguous\t1\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_names() {
+        let dir = output_dir("demux-names");
+        for name in [
+            "", "..", "../x", "a/b", "/tmp/x", "a\\b", UNASSIGNED, AMBIGUOUS,
+        ] {
+            let mut dsq = Disambiseq::named();
+            dsq.insert_named("ACT", name);
+            let demux = Demultiplexer::new(&dsq, 0, 3, &dir)
+                .unwrap()
+                .with_namer(|_, name| name.unwrap().to_string());
+            match demux {
+                Err(Error::InvalidOutputName { name: n, .. }) => assert_eq!(n, name),
+                Err(e) => panic!("unexpected error: {e}"),
+                Ok(_) => panic!("accepted invalid name '{name}'"),
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn max_open_files() {
+        let dir = output_dir("demux-open");
+        let mut dsq = Disambiseq::named();
+        dsq.insert_named("ACT", "sample1");
+        dsq.insert_named("AGT", "sample2");
+        let mut demux = Demultiplexer::new(&dsq, 0, 3, &dir)
+            .unwrap()
+            .with_namer(|p, name| name.map_or(p.sequence().to_string(), |n| n.to_string()))
+            .unwrap()
+            .with_max_open_files(1);
+        demux.run(FASTQ.as_bytes()).unwrap();
+        demux.finish().unwrap();
+
+        // outputs closed to respect the limit are appended to when reopened
+        let sample1 = fs::read_to_string(dir.join("sample1.fastq")).unwrap();
+        assert_eq!(sample1, "@r1\nACTGG\n+\nIIIII\n@r2\nTCTGG\n+\nIIIII\n");
+        let sample2 = fs::read_to_string(dir.join("sample2.fastq")).unwrap();
+        assert_eq!(sample2, "@r5\nAGTGG\n+\nIIIII\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_paired() {
+        let dir = output_dir("demux-paired");
+        let dsq = Disambiseq::from_slice(&["ACT".to_string(), "AGT".to_string()]);
+        let r2 = FASTQ.replace("GG\n", "CC\n");
+
+        let mut demux = Demultiplexer::new(&dsq, 0, 3, &dir).unwrap();
+        demux.run_paired(FASTQ.as_bytes(), r2.as_bytes()).unwrap();
+        let summary = demux.finish().unwrap();
+        assert_eq!(summary.counts["ACT"], 2);
+
+        let r1 = fs::read_to_string(dir.join("ACT_R1.fastq")).unwrap();
+        let r2 = fs::read_to_string(dir.join("ACT_R2.fastq")).unwrap();
+        assert_eq!(r1.lines().nth(1), Some("ACTGG"));
+        assert_eq!(r2.lines().nth(1), Some("ACTCC"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_paired_mismatched() {
+        let dir = output_dir("demux-mismatched");
+        let dsq = Disambiseq::from_slice(&["ACT".to_string()]);
+        let r2 = "@r1\nACTGG\n+\nIIIII\n";
+        let mut demux = Demultiplexer::new(&dsq, 0, 3, &dir).unwrap();
+        assert!(demux.run_paired(FASTQ.as_bytes(), r2.as_bytes()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_mates() {
+        let dir = output_dir("demux-mates");
+        let dsq = Disambiseq::from_slice(&["ACTGG".to_string()]);
+        let i1 = "@r1/3\nACT\n+\nIII\n@r2/3\nACT\n+\nIII\n";
+        let r1 = "@r1/1\nNNGGAA\n+\nIIIIII\n@r2/1\nNNCCAA\n+\nIIIIII\n";
+        let r2 = "@r1/2\nTTTT\n+\nIIII\n@r2/2\nCCCC\n+\nIIII\n";
+
+        let structure = "3B 2S2B+T +T".parse().unwrap();
+        let mut demux = Demultiplexer::new(&dsq, 0, 0, &dir)
+            .unwrap()
+            .with_structure(structure);
+        demux
+            .run_mates(vec![i1.as_bytes(), r1.as_bytes(), r2.as_bytes()])
+            .unwrap();
+        let summary = demux.finish().unwrap();
+        assert_eq!(summary.counts["ACTGG"], 1);
+        assert_eq!(summary.unassigned, 1);
+
+        let r3 = fs::read_to_string(dir.join("ACTGG_R3.fastq")).unwrap();
+        assert_eq!(r3, "@r1/2\nTTTT\n+\nIIII\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_paired_structure() {
+        let dir = output_dir("demux-paired-structure");
+        let dsq = Disambiseq::from_slice(&["GGCC".to_string()]);
+        let r2 = FASTQ.replace("GG\n", "CC\n");
+
+        // the barcode spans the last two bases of each mate
+        let structure = "3S2B 3S2B".parse().unwrap();
+        let mut demux = Demultiplexer::new(&dsq, 0, 0, &dir)
+            .unwrap()
+            .with_structure(structure);
+        demux.run_paired(FASTQ.as_bytes(), r2.as_bytes()).unwrap();
+        let summary = demux.finish().unwrap();
+        assert_eq!(summary.counts["GGCC"], 5);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_mates_count() {
+        let dir = output_dir("demux-mates-count");
+        let dsq = Disambiseq::from_slice(&["ACT".to_string()]);
+        let structure = "3B +T +T".parse().unwrap();
+        let mut demux = Demultiplexer::new(&dsq, 0, 0, &dir)
+            .unwrap()
+            .with_structure(structure);
+        assert!(matches!(
+            demux.run_mates(vec![FASTQ.as_bytes(), FASTQ.as_bytes()]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+        assert!(matches!(
+            demux.run_paired(FASTQ.as_bytes(), FASTQ.as_bytes()),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+        let record = FastqReader::new(FASTQ.as_bytes()).next().unwrap().unwrap();
+        assert!(demux.process_mates(&[&record]).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn demultiplex_mates_desynchronized() {
+        let dir = output_dir("demux-desync");
+        let dsq = Disambiseq::from_slice(&["ACT".to_string()]);
+        let r2 = FASTQ.replace("@r2", "@r9");
+        let mut demux = Demultiplexer::new(&dsq, 0, 3, &dir).unwrap();
+        match demux.run_mates(vec![FASTQ.as_bytes(), r2.as_bytes()]) {
+            Err(Error::Desynchronized { record, .. }) => assert_eq!(record, 2),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
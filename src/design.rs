@@ -0,0 +1,286 @@
+pub use crate::distance::DistanceMetric;
+use crate::{
+    error::{Error, Result},
+    utils::try_reverse_complement_bytes,
+    Disambiseq,
+};
+
+/// A small seeded pseudo-random generator (SplitMix64) so designs are
+/// reproducible without extra dependencies
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Generates barcode sets with a guaranteed minimum pairwise distance.
+///
+/// Random candidates are drawn from a seeded generator and greedily kept
+/// if they satisfy the sequence constraints and are far enough from every
+/// barcode kept so far. A minimum distance of 3 (the default) guarantees
+/// that every one-mismatch alias is unambiguous.
+#[derive(Debug, Clone)]
+pub struct BarcodeDesigner {
+    count: usize,
+    length: usize,
+    min_distance: usize,
+    metric: DistanceMetric,
+    gc: (f64, f64),
+    max_homopolymer: usize,
+    forbidden: Vec<String>,
+    reserved: Vec<String>,
+    seed: u64,
+    max_attempts: usize,
+}
+impl BarcodeDesigner {
+    /// Creates a designer of `count` barcodes of `length` bases
+    pub fn new(count: usize, length: usize) -> Self {
+        Self {
+            count,
+            length,
+            min_distance: 3,
+            metric: DistanceMetric::Hamming,
+            gc: (0.0, 1.0),
+            max_homopolymer: length,
+            forbidden: Vec::new(),
+            reserved: Vec::new(),
+            seed: 0,
+            max_attempts: 100_000,
+        }
+    }
+
+    /// Sets the minimum distance between every pair of barcodes
+    pub fn with_min_distance(mut self, distance: usize, metric: DistanceMetric) -> Self {
+        self.min_distance = distance;
+        self.metric = metric;
+        self
+    }
+
+    /// Sets the inclusive bounds of the GC fraction of each barcode
+    pub fn with_gc(mut self, min: f64, max: f64) -> Self {
+        self.gc = (min, max);
+        self
+    }
+
+    /// Sets the longest run of a single base allowed
+    pub fn with_max_homopolymer(mut self, length: usize) -> Self {
+        self.max_homopolymer = length;
+        self
+    }
+
+    /// Rejects barcodes containing a motif (e.g. a restriction site) on
+    /// either strand
+    pub fn with_forbidden_motif(mut self, motif: &str) -> Self {
+        self.forbidden.push(motif.to_ascii_uppercase());
+        self
+    }
+
+    /// Keeps the designed barcodes at the minimum distance from existing
+    /// barcodes, e.g. to replace the dropped parents of a library
+    pub fn with_reserved<S: AsRef<str>>(mut self, barcodes: &[S]) -> Self {
+        self.reserved
+            .extend(barcodes.iter().map(|b| b.as_ref().to_ascii_uppercase()));
+        self
+    }
+
+    /// Sets the seed of the random generator
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets how many candidates are drawn in a row without success before
+    /// giving up
+    pub fn with_max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Returns true if a candidate satisfies the GC, homopolymer and motif
+    /// constraints. The candidate is compared case-insensitively and is
+    /// invalid if it contains characters other than `ACGTN`.
+    pub fn is_valid(&self, barcode: &str) -> bool {
+        let barcode = barcode.to_ascii_uppercase();
+        let Some(rc) = try_reverse_complement_bytes(barcode.as_bytes()) else {
+            return false;
+        };
+        let gc = barcode.bytes().filter(|b| matches!(b, b'G' | b'C')).count() as f64
+            / barcode.len().max(1) as f64;
+        if gc < self.gc.0 || gc > self.gc.1 {
+            return false;
+        }
+        let mut run = 0;
+        let mut previous = None;
+        for base in barcode.bytes() {
+            run = if previous == Some(base) { run + 1 } else { 1 };
+            previous = Some(base);
+            if run > self.max_homopolymer {
+                return false;
+            }
+        }
+        // the reverse complement only contains ascii nucleotides
+        let rc = String::from_utf8(rc).unwrap();
+        !self
+            .forbidden
+            .iter()
+            .any(|motif| barcode.contains(motif.as_str()) || rc.contains(motif.as_str()))
+    }
+
+    fn candidate(&self, rng: &mut SplitMix64) -> String {
+        (0..self.length)
+            .map(|_| ['A', 'C', 'G', 'T'][(rng.next() >> 62) as usize])
+            .collect()
+    }
+
+    /// Designs the barcode set, failing if fewer than the requested number
+    /// of barcodes could be found
+    pub fn design(&self) -> Result<Vec<String>> {
+        let mut rng = SplitMix64(self.seed);
+        let mut barcodes: Vec<String> = Vec::with_capacity(self.count);
+        let mut attempts = 0;
+        while barcodes.len() < self.count && attempts < self.max_attempts {
+            attempts += 1;
+            let candidate = self.candidate(&mut rng);
+            if !self.is_valid(&candidate) {
+                continue;
+            }
+            let far = self.reserved.iter().chain(&barcodes).all(|b| {
+                self.metric.distance(b.as_bytes(), candidate.as_bytes()) >= self.min_distance
+            });
+            if far {
+                barcodes.push(candidate);
+                attempts = 0;
+            }
+        }
+        if barcodes.len() < self.count {
+            return Err(Error::DesignExhausted {
+                requested: self.count,
+                designed: barcodes.len(),
+            });
+        }
+        Ok(barcodes)
+    }
+
+    /// Designs the barcode set and builds its index
+    pub fn design_index(&self) -> Result<Disambiseq> {
+        Ok(Disambiseq::from_slice(&self.design()?))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::utils::{hamming_distance, levenshtein_distance};
+
+    #[test]
+    fn constraints() {
+        let designer = BarcodeDesigner::new(1, 8)
+            .with_gc(0.25, 0.75)
+            .with_max_homopolymer(2)
+            .with_forbidden_motif("GAATTC");
+        assert!(designer.is_valid("ACGTACGT"));
+        assert!(!designer.is_valid("AAATACGT"));
+        assert!(!designer.is_valid("ATATATAT"));
+        assert!(!designer.is_valid("CGAATTCT"));
+        let designer = designer.with_forbidden_motif("GGATCC");
+        assert!(!designer.is_valid("AGGATCCA"));
+        // CCTA only occurs on the reverse strand
+        let designer = BarcodeDesigner::new(1, 8).with_forbidden_motif("CCTA");
+        assert!(!designer.is_valid("ATAGGTTC"));
+
+        // user-supplied barcodes are checked without panicking
+        assert!(!designer.is_valid("ataggttc"));
+        assert!(designer.is_valid("acgtacgt"));
+        assert!(!designer.is_valid("ACGTXCGT"));
+    }
+
+    #[test]
+    fn max_homopolymer_zero() {
+        // every base is a run of one
+        let designer = BarcodeDesigner::new(1, 4)
+            .with_max_homopolymer(0)
+            .with_max_attempts(100);
+        assert!(!designer.is_valid("A"));
+        assert!(!designer.is_valid("ACGT"));
+        assert!(matches!(
+            designer.design(),
+            Err(Error::DesignExhausted { designed: 0, .. })
+        ));
+        assert!(BarcodeDesigner::new(1, 4)
+            .with_max_homopolymer(1)
+            .is_valid("ACGT"));
+    }
+
+    #[test]
+    fn design_hamming() {
+        let designer = BarcodeDesigner::new(48, 8)
+            .with_gc(0.375, 0.625)
+            .with_max_homopolymer(2)
+            .with_forbidden_motif("GAATTC")
+            .with_seed(7);
+        let barcodes = designer.design().unwrap();
+        assert_eq!(barcodes.len(), 48);
+        assert_eq!(barcodes, designer.design().unwrap());
+        for (i, a) in barcodes.iter().enumerate() {
+            assert!(designer.is_valid(a));
+            for b in &barcodes[i + 1..] {
+                assert!(hamming_distance(a.as_bytes(), b.as_bytes()).unwrap() >= 3);
+            }
+        }
+
+        let index = designer.design_index().unwrap();
+        assert_eq!(index.parents().len(), 48);
+        assert!(index.ambiguous().is_empty());
+    }
+
+    #[test]
+    fn design_levenshtein() {
+        let barcodes = BarcodeDesigner::new(16, 6)
+            .with_min_distance(3, DistanceMetric::Levenshtein)
+            .design()
+            .unwrap();
+        for (i, a) in barcodes.iter().enumerate() {
+            for b in &barcodes[i + 1..] {
+                assert!(levenshtein_distance(a.as_bytes(), b.as_bytes()) >= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn design_reserved() {
+        let reserved = BarcodeDesigner::new(8, 6).with_seed(1).design().unwrap();
+        let lowercase: Vec<String> = reserved.iter().map(|b| b.to_ascii_lowercase()).collect();
+        // the same seed would otherwise draw the reserved barcodes again
+        let barcodes = BarcodeDesigner::new(8, 6)
+            .with_seed(1)
+            .with_reserved(&lowercase)
+            .design()
+            .unwrap();
+        for barcode in &barcodes {
+            for r in &reserved {
+                assert!(hamming_distance(barcode.as_bytes(), r.as_bytes()).unwrap() >= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn design_exhausted() {
+        // no two barcodes of length 2 can be 3 mismatches apart
+        match BarcodeDesigner::new(2, 2).with_max_attempts(1000).design() {
+            Err(Error::DesignExhausted {
+                requested,
+                designed,
+            }) => {
+                assert_eq!(requested, 2);
+                assert_eq!(designed, 1);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+}
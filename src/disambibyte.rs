@@ -1,6 +1,14 @@
-use crate::{sequence::ByteSequence, utils::reverse_complement_bytes};
+use crate::{
+    disambiseq::Alias,
+    sequence::ByteSequence,
+    strand::{Strand, StrandPolicy},
+    utils::{canonical_bytes, is_palindrome, reverse_complement_bytes},
+};
 use hashbrown::{HashMap, HashSet};
-use std::{borrow::Borrow, sync::Arc};
+use std::{
+    borrow::{Borrow, Cow},
+    sync::Arc,
+};
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 pub struct ByteWrapper(pub Arc<Vec<u8>>);
@@ -17,17 +25,60 @@ impl ByteWrapper {
 
 #[derive(Debug, Clone, Default)]
 pub struct Disambibyte {
-    unambiguous: HashMap<ByteWrapper, ByteWrapper>,
+    unambiguous: HashMap<ByteWrapper, Alias<ByteWrapper>>,
     parents: HashSet<ByteWrapper>,
+    ids: HashMap<ByteWrapper, usize>,
+    records: Vec<(ByteWrapper, StrandPolicy)>,
     ambiguous: HashSet<ByteWrapper>,
     null: HashSet<ByteWrapper>,
+    palindromes: HashSet<ByteWrapper>,
+    canonical: bool,
 }
 impl Disambibyte {
     pub fn new() -> Self {
         Self::default()
     }
 
-    fn insert_alias(&mut self, child: Vec<u8>, parent: &ByteWrapper) {
+    /// Creates an index in canonical mode.
+    ///
+    /// Every sequence is stored as the lexicographic minimum of itself and
+    /// its reverse complement, making the index strand-agnostic at half the
+    /// memory of indexing both strands. Queries are canonicalized before
+    /// lookup.
+    pub fn canonical() -> Self {
+        Self {
+            canonical: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the index is in canonical mode
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Returns the key a sequence is stored under
+    fn key<'a>(&self, seq: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.canonical {
+            canonical_bytes(seq)
+        } else {
+            Cow::Borrowed(seq)
+        }
+    }
+
+    /// Adds a new parent and its strand policy, returning the shared
+    /// parent sequence and its id
+    fn push_parent(&mut self, parent: &[u8], policy: StrandPolicy) -> (ByteWrapper, usize) {
+        let parent = ByteWrapper(Arc::new(parent.to_vec()));
+        let id = self.records.len();
+        self.parents.insert(parent.clone());
+        self.ids.insert(parent.clone(), id);
+        self.records.push((parent.clone(), policy));
+        self.unambiguous.remove(&parent);
+        (parent, id)
+    }
+
+    fn insert_alias(&mut self, child: Vec<u8>, parent: &ByteWrapper, strand: Strand, id: usize) {
         let child = ByteWrapper(Arc::new(child));
 
         // skip ambigiuous or parental sequences
@@ -38,6 +89,17 @@ impl Disambibyte {
             return;
         }
 
+        // the same parent may generate a sequence on both strands, in which
+        // case the forward strand is kept
+        if let Some(alias) = self.unambiguous.get_mut(&child) {
+            if alias.id == id {
+                if strand == Strand::Forward {
+                    alias.strand = strand;
+                }
+                return;
+            }
+        }
+
         // if the sequence has seen before it becomes ambiguous
         if self.unambiguous.contains_key(&child) {
             self.ambiguous.insert(child.clone());
@@ -45,56 +107,118 @@ impl Disambibyte {
 
         // purely unambiguous sequence found
         } else {
-            self.unambiguous.insert(child.clone(), parent.clone());
+            self.unambiguous.insert(
+                child,
+                Alias {
+                    parent: parent.clone(),
+                    strand,
+                    id,
+                },
+            );
         }
     }
 
     /// Inserts a parent sequence with which to create all unambiguous
     /// point mutations.
     pub fn insert(&mut self, parent: &[u8]) {
+        self.insert_stranded(parent, StrandPolicy::Forward);
+    }
+
+    /// Inserts a parent sequence with which to create all unambiguous
+    /// point mutations as well as the reverse complement of those sequences.
+    pub fn insert_with_reverse_complement(&mut self, parent: &[u8]) {
+        self.insert_stranded(parent, StrandPolicy::Both);
+    }
+
+    /// Inserts a parent sequence indexing the strands selected by the policy.
+    ///
+    /// The exact reverse complement of a parent takes priority over point
+    /// mutations of other parents but never over another parent, and
+    /// palindromic parents are only indexed once. Parents that are not
+    /// indexed on the forward strand do not match themselves.
+    ///
+    /// In canonical mode both strands are always indexed.
+    pub fn insert_stranded(&mut self, parent: &[u8], policy: StrandPolicy) {
         if self.parents.contains(parent) {
             return;
         }
+        if self.canonical {
+            return self.insert_canonical(parent);
+        }
 
-        let parent = ByteWrapper(Arc::new(parent.to_vec()));
-        self.parents.insert(parent.clone());
+        let (parent, id) = self.push_parent(parent, policy);
+
+        if policy.reverse() {
+            let parent_revc = ByteWrapper(Arc::new(reverse_complement_bytes(parent.borrow())));
 
-        if self.unambiguous.contains_key(&parent) {
-            self.unambiguous.remove(&parent);
+            // insert parent reverse complement unless it is a parent itself
+            if !self.parents.contains(&parent_revc) {
+                self.unambiguous.insert(
+                    parent_revc.clone(),
+                    Alias {
+                        parent: parent.clone(),
+                        strand: Strand::Reverse,
+                        id,
+                    },
+                );
+                self.ambiguous.remove(&parent_revc);
+
+                // blacklist reverse complement of parent
+                self.null.insert(parent_revc);
+            }
         }
 
         ByteSequence::new(parent.borrow())
             .mutate_all()
             .into_iter()
-            .for_each(|x| self.insert_alias(x, &parent));
+            .for_each(|x| {
+                if policy.reverse() {
+                    self.insert_alias(reverse_complement_bytes(&x), &parent, Strand::Reverse, id);
+                }
+                if policy.forward() {
+                    self.insert_alias(x, &parent, Strand::Forward, id);
+                }
+            });
     }
 
-    /// Inserts a parent sequence with which to create all unambiguous
-    /// point mutations as well as the reverse complement of those sequences.
-    pub fn insert_with_reverse_complement(&mut self, parent: &[u8]) {
-        if self.parents.contains(parent) {
-            return;
-        }
-        let parent_revc = ByteWrapper(Arc::new(reverse_complement_bytes(parent)));
-        let parent = ByteWrapper(Arc::new(parent.to_vec()));
-        self.parents.insert(parent.clone());
+    /// Inserts a parent sequence storing the canonical form of itself and
+    /// all of its point mutations.
+    ///
+    /// Palindromic parents are recorded and their aliases, which are shared
+    /// between both strands, are only indexed once.
+    fn insert_canonical(&mut self, parent: &[u8]) {
+        let (parent, id) = self.push_parent(parent, StrandPolicy::Both);
 
-        if self.unambiguous.contains_key(&parent) {
-            self.unambiguous.remove(&parent);
+        if is_palindrome(parent.sequence()) {
+            self.palindromes.insert(parent.clone());
         }
 
-        // insert parent reverse_complement with potential overwriting
-        self.unambiguous.insert(parent_revc.clone(), parent.clone());
-
-        // blacklist reverse complement of parent
-        self.null.insert(parent_revc.clone());
+        // store a non-canonical parent under its canonical key
+        let key = ByteWrapper(Arc::new(canonical_bytes(parent.borrow()).into_owned()));
+        if key != parent && !self.parents.contains(&key) {
+            self.unambiguous.insert(
+                key.clone(),
+                Alias {
+                    parent: parent.clone(),
+                    strand: Strand::Reverse,
+                    id,
+                },
+            );
+            self.ambiguous.remove(&key);
+            self.null.insert(key);
+        }
 
         ByteSequence::new(parent.borrow())
             .mutate_all()
             .into_iter()
             .for_each(|x| {
-                self.insert_alias(reverse_complement_bytes(&x), &parent);
-                self.insert_alias(x, &parent);
+                let key = canonical_bytes(&x).into_owned();
+                let strand = if key == x {
+                    Strand::Forward
+                } else {
+                    Strand::Reverse
+                };
+                self.insert_alias(key, &parent, strand, id)
             });
     }
 
@@ -103,27 +227,63 @@ impl Disambibyte {
         sequences.iter().for_each(|x| dsb.insert(x));
         dsb
     }
-    pub fn get_parent(&self, seq: &[u8]) -> Option<&ByteWrapper> {
-        if let Some(p) = self.parents.get(seq) {
-            Some(p)
-        } else {
-            self.unambiguous.get(seq)
+    /// Resolves a sequence to the id of its parent and the strand of the
+    /// parent it matched
+    fn resolve(&self, seq: &[u8]) -> Option<(usize, Strand)> {
+        if let Some(&id) = self.ids.get(seq) {
+            if self.records[id].1.forward() {
+                return Some((id, Strand::Forward));
+            }
+            // a palindrome is its own reverse complement
+            if is_palindrome(seq) {
+                return Some((id, Strand::Reverse));
+            }
         }
+        let key = self.key(seq);
+        // canonical queries stored as their reverse complement
+        let flipped = key.as_ref() != seq;
+        if flipped {
+            if let Some(&id) = self.ids.get(key.as_ref()) {
+                return Some((id, Strand::Reverse));
+            }
+        }
+        let alias = self.unambiguous.get(key.as_ref())?;
+        let strand = if flipped {
+            alias.strand.flip()
+        } else {
+            alias.strand
+        };
+        Some((alias.id, strand))
+    }
+    pub fn get_parent(&self, seq: &[u8]) -> Option<&ByteWrapper> {
+        self.resolve(seq).map(|(id, _)| &self.records[id].0)
+    }
+    /// Retrieves the parent of a sequence and the strand it was matched on
+    pub fn get_parent_with_strand(&self, seq: &[u8]) -> Option<(&ByteWrapper, Strand)> {
+        self.resolve(seq)
+            .map(|(id, strand)| (&self.records[id].0, strand))
     }
     pub fn parents(&self) -> &HashSet<ByteWrapper> {
         &self.parents
     }
+    /// Returns the palindromic parents found in canonical mode
+    pub fn palindromes(&self) -> &HashSet<ByteWrapper> {
+        &self.palindromes
+    }
     pub fn ambiguous(&self) -> &HashSet<ByteWrapper> {
         &self.ambiguous
     }
-    pub fn unambiguous(&self) -> &HashMap<ByteWrapper, ByteWrapper> {
+    /// Returns every unambiguous alias with its parent and strand
+    pub fn unambiguous(&self) -> &HashMap<ByteWrapper, Alias<ByteWrapper>> {
         &self.unambiguous
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod testing {
     use super::Disambibyte;
+    use crate::strand::{Strand, StrandPolicy};
 
     #[test]
     fn init_slice() {
@@ -181,4 +341,46 @@ mod testing {
         assert_eq!(dsb.get_parent(b"AGTAA").unwrap().sequence(), b"AGTAA");
         assert_eq!(dsb.get_parent(b"ATAGT").unwrap().sequence(), b"ACTAA");
     }
+
+    #[test]
+    fn strand_get() {
+        let mut dsb = Disambibyte::new();
+        dsb.insert_with_reverse_complement(b"ACTAA");
+        let (parent, strand) = dsb.get_parent_with_strand(b"ACTAT").unwrap();
+        assert_eq!(
+            (parent.sequence(), strand),
+            (&b"ACTAA"[..], Strand::Forward)
+        );
+        let (parent, strand) = dsb.get_parent_with_strand(b"TTAGA").unwrap();
+        assert_eq!(
+            (parent.sequence(), strand),
+            (&b"ACTAA"[..], Strand::Reverse)
+        );
+    }
+
+    #[test]
+    fn strand_policy_reverse() {
+        let mut dsb = Disambibyte::new();
+        dsb.insert_stranded(b"ACTAA", StrandPolicy::Reverse);
+        assert_eq!(dsb.unambiguous().len(), 16);
+        assert_eq!(dsb.get_parent(b"ACTAT"), None);
+        assert_eq!(dsb.get_parent(b"TTAGA").unwrap().sequence(), b"ACTAA");
+
+        // the forward parent itself is not indexed
+        assert_eq!(dsb.get_parent(b"ACTAA"), None);
+        let (_, strand) = dsb.get_parent_with_strand(b"TTAGT").unwrap();
+        assert_eq!(strand, Strand::Reverse);
+    }
+
+    #[test]
+    fn canonical_get() {
+        let mut dsb = Disambibyte::canonical();
+        dsb.insert(b"ACTAA");
+        dsb.insert(b"AGTAA");
+        assert_eq!(dsb.ambiguous().len(), 2);
+        assert_eq!(dsb.unambiguous().len(), 24);
+        assert_eq!(dsb.get_parent(b"TTAGT").unwrap().sequence(), b"ACTAA");
+        assert_eq!(dsb.get_parent(b"ATAGT").unwrap().sequence(), b"ACTAA");
+        assert_eq!(dsb.get_parent(b"TTAAT"), None);
+    }
 }
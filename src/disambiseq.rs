@@ -1,6 +1,15 @@
-use std::{borrow::Borrow, sync::Arc};
+use std::{
+    borrow::{Borrow, Cow},
+    sync::Arc,
+};
 
-use crate::{sequence::Sequence, utils::reverse_complement};
+use crate::{
+    prior::AbundancePrior,
+    sequence::Sequence,
+    strand::{Strand, StrandPolicy},
+    two_pass::Assignment,
+    utils::{canonical, is_palindrome, reverse_complement},
+};
 use hashbrown::{HashMap, HashSet};
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -16,115 +25,433 @@ impl SeqWrapper {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct Disambiseq {
-    unambiguous: HashMap<SeqWrapper, SeqWrapper>,
+/// The parent an unambiguous sequence resolves to and the strand of the
+/// parent it was generated from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias<W = SeqWrapper> {
+    pub parent: W,
+    pub strand: Strand,
+    /// Index of the parent record
+    pub(crate) id: usize,
+}
+
+/// A parent with the strands it was indexed on and its payload
+#[derive(Debug, Clone)]
+struct ParentRecord<T> {
+    parent: SeqWrapper,
+    policy: StrandPolicy,
+    payload: Option<T>,
+}
+
+/// An index of unambiguous point mutations for a set of parent sequences.
+///
+/// Parents may optionally carry a payload `T` (e.g. a guide or sample
+/// record) which is returned directly by lookups.
+#[derive(Debug, Clone)]
+pub struct Disambiseq<T = ()> {
+    unambiguous: HashMap<SeqWrapper, Alias>,
     parents: HashSet<SeqWrapper>,
+    ids: HashMap<SeqWrapper, usize>,
+    records: Vec<ParentRecord<T>>,
+    /// The distinct parent lengths in ascending order
+    lengths: Vec<usize>,
     ambiguous: HashSet<SeqWrapper>,
+    candidates: HashMap<SeqWrapper, Vec<SeqWrapper>>,
     null: HashSet<SeqWrapper>,
+    palindromes: HashSet<SeqWrapper>,
+    duplicates: HashMap<SeqWrapper, usize>,
+    canonical: bool,
+}
+impl<T> Default for Disambiseq<T> {
+    fn default() -> Self {
+        Self {
+            unambiguous: HashMap::new(),
+            parents: HashSet::new(),
+            ids: HashMap::new(),
+            records: Vec::new(),
+            lengths: Vec::new(),
+            ambiguous: HashSet::new(),
+            candidates: HashMap::new(),
+            null: HashSet::new(),
+            palindromes: HashSet::new(),
+            duplicates: HashMap::new(),
+            canonical: false,
+        }
+    }
 }
 impl Disambiseq {
     pub fn new() -> Self {
         Self::default()
     }
 
-    fn insert_alias(&mut self, child: String, parent: &SeqWrapper) {
+    /// Creates an index in canonical mode.
+    ///
+    /// Every sequence is stored as the lexicographic minimum of itself and
+    /// its reverse complement, making the index strand-agnostic at half the
+    /// memory of indexing both strands. Queries are canonicalized before
+    /// lookup.
+    pub fn canonical() -> Self {
+        Self::named_canonical()
+    }
+
+    pub fn from_slice(sequences: &[String]) -> Self {
+        let mut dsq = Self::new();
+        sequences.iter().for_each(|x| dsq.insert(x));
+        dsq
+    }
+}
+impl<T> Disambiseq<T> {
+    /// Creates an index whose parents carry a payload of type `T`
+    pub fn named() -> Self {
+        Self::default()
+    }
+
+    /// Creates an index in canonical mode whose parents carry a payload
+    /// of type `T`
+    pub fn named_canonical() -> Self {
+        Self {
+            canonical: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the index is in canonical mode
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Returns the key a sequence is stored under
+    fn key<'a>(&self, seq: &'a str) -> Cow<'a, str> {
+        if self.canonical {
+            canonical(seq)
+        } else {
+            Cow::Borrowed(seq)
+        }
+    }
+
+    /// Counts a repeated insertion of an existing parent, returning true
+    /// if the parent was already present
+    fn record_duplicate(&mut self, parent: &str) -> bool {
+        match self.parents.get(parent) {
+            Some(p) => {
+                *self.duplicates.entry(p.clone()).or_default() += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a new parent and its record, returning the shared parent
+    /// sequence and its id
+    fn push_parent(&mut self, parent: &str, policy: StrandPolicy) -> (SeqWrapper, usize) {
+        let parent = SeqWrapper(Arc::new(parent.to_string()));
+        let id = self.records.len();
+        self.parents.insert(parent.clone());
+        self.ids.insert(parent.clone(), id);
+        self.records.push(ParentRecord {
+            parent: parent.clone(),
+            policy,
+            payload: None,
+        });
+        if let Err(pos) = self.lengths.binary_search(&parent.0.len()) {
+            self.lengths.insert(pos, parent.0.len());
+        }
+        self.unambiguous.remove(&parent);
+        (parent, id)
+    }
+
+    fn insert_alias(&mut self, child: String, parent: &SeqWrapper, strand: Strand, id: usize) {
         let child = SeqWrapper(Arc::new(child));
 
-        // skip ambigiuous or parental sequences
-        if self.ambiguous.contains(&child)
-            | self.parents.contains(&child)
-            | self.null.contains(&child)
-        {
+        // skip parental or blacklisted sequences
+        if self.parents.contains(&child) | self.null.contains(&child) {
             return;
         }
 
+        // record the additional candidate parent of an ambiguous sequence
+        if self.ambiguous.contains(&child) {
+            if let Some(candidates) = self.candidates.get_mut(&child) {
+                if !candidates.contains(parent) {
+                    candidates.push(parent.clone());
+                }
+            }
+            return;
+        }
+
+        // the same parent may generate a sequence on both strands, in which
+        // case the forward strand is kept
+        if let Some(alias) = self.unambiguous.get_mut(&child) {
+            if alias.id == id {
+                if strand == Strand::Forward {
+                    alias.strand = strand;
+                }
+                return;
+            }
+        }
+
         // if the sequence has seen before it becomes ambiguous
-        if self.unambiguous.contains_key(&child) {
+        if let Some(previous) = self.unambiguous.remove(&child) {
             self.ambiguous.insert(child.clone());
-            self.unambiguous.remove(&child);
+            self.candidates
+                .insert(child, vec![previous.parent, parent.clone()]);
 
         // purely unambiguous sequence found
         } else {
-            self.unambiguous.insert(child.clone(), parent.clone());
+            self.unambiguous.insert(
+                child,
+                Alias {
+                    parent: parent.clone(),
+                    strand,
+                    id,
+                },
+            );
         }
     }
 
     /// Inserts a parent sequence with which to create all unambiguous
     /// point mutations.
     pub fn insert(&mut self, parent: &str) {
-        if self.parents.contains(parent) {
+        self.insert_stranded(parent, StrandPolicy::Forward);
+    }
+
+    /// Inserts a parent sequence with which to create all unambiguous
+    /// point mutations as well as the reverse complement of those sequences.
+    pub fn insert_with_reverse_complement(&mut self, parent: &str) {
+        self.insert_stranded(parent, StrandPolicy::Both);
+    }
+
+    /// Inserts a parent sequence indexing the strands selected by the policy.
+    ///
+    /// The exact reverse complement of a parent takes priority over point
+    /// mutations of other parents but never over another parent, and
+    /// palindromic parents are only indexed once. Parents that are not
+    /// indexed on the forward strand do not match themselves.
+    ///
+    /// In canonical mode both strands are always indexed.
+    pub fn insert_stranded(&mut self, parent: &str, policy: StrandPolicy) {
+        if self.record_duplicate(parent) {
             return;
         }
+        if self.canonical {
+            return self.insert_canonical(parent);
+        }
 
-        let parent = SeqWrapper(Arc::new(parent.to_string()));
-        self.parents.insert(parent.clone());
+        let (parent, id) = self.push_parent(parent, policy);
 
-        if self.unambiguous.contains_key(&parent) {
-            self.unambiguous.remove(&parent);
+        if policy.reverse() {
+            let parent_revc = SeqWrapper(Arc::new(reverse_complement(parent.borrow())));
+
+            // insert parent reverse complement unless it is a parent itself
+            if !self.parents.contains(&parent_revc) {
+                self.unambiguous.insert(
+                    parent_revc.clone(),
+                    Alias {
+                        parent: parent.clone(),
+                        strand: Strand::Reverse,
+                        id,
+                    },
+                );
+                self.ambiguous.remove(&parent_revc);
+                self.candidates.remove(&parent_revc);
+
+                // blacklist reverse complement of parent
+                self.null.insert(parent_revc);
+            }
         }
 
         Sequence::new(parent.borrow())
             .mutate_all()
             .into_iter()
-            .for_each(|x| self.insert_alias(x, &parent));
+            .for_each(|x| {
+                if policy.reverse() {
+                    self.insert_alias(reverse_complement(&x), &parent, Strand::Reverse, id);
+                }
+                if policy.forward() {
+                    self.insert_alias(x, &parent, Strand::Forward, id);
+                }
+            });
     }
 
-    /// Inserts a parent sequence with which to create all unambiguous
-    /// point mutations as well as the reverse complement of those sequences.
-    pub fn insert_with_reverse_complement(&mut self, parent: &str) {
-        if self.parents.contains(parent) {
-            return;
-        }
-        let parent_revc = SeqWrapper(Arc::new(reverse_complement(parent)));
-        let parent = SeqWrapper(Arc::new(parent.to_string()));
-        self.parents.insert(parent.clone());
+    /// Inserts a parent sequence storing the canonical form of itself and
+    /// all of its point mutations.
+    ///
+    /// Palindromic parents are recorded and their aliases, which are shared
+    /// between both strands, are only indexed once.
+    fn insert_canonical(&mut self, parent: &str) {
+        let (parent, id) = self.push_parent(parent, StrandPolicy::Both);
 
-        if self.unambiguous.contains_key(&parent) {
-            self.unambiguous.remove(&parent);
+        if is_palindrome(parent.sequence().as_bytes()) {
+            self.palindromes.insert(parent.clone());
         }
 
-        // insert parent reverse_complement with potential overwriting
-        self.unambiguous.insert(parent_revc.clone(), parent.clone());
-
-        // blacklist reverse complement of parent
-        self.null.insert(parent_revc.clone());
+        // store a non-canonical parent under its canonical key
+        let key = SeqWrapper(Arc::new(canonical(parent.borrow()).into_owned()));
+        if key != parent && !self.parents.contains(&key) {
+            self.unambiguous.insert(
+                key.clone(),
+                Alias {
+                    parent: parent.clone(),
+                    strand: Strand::Reverse,
+                    id,
+                },
+            );
+            self.ambiguous.remove(&key);
+            self.candidates.remove(&key);
+            self.null.insert(key);
+        }
 
         Sequence::new(parent.borrow())
             .mutate_all()
             .into_iter()
             .for_each(|x| {
-                self.insert_alias(reverse_complement(&x), &parent);
-                self.insert_alias(x, &parent);
+                let key = canonical(&x).into_owned();
+                let strand = if key == x {
+                    Strand::Forward
+                } else {
+                    Strand::Reverse
+                };
+                self.insert_alias(key, &parent, strand, id)
             });
     }
 
-    pub fn from_slice(sequences: &[String]) -> Self {
-        let mut dsq = Self::new();
-        sequences.iter().for_each(|x| dsq.insert(x));
-        dsq
+    /// Inserts a parent sequence along with its payload.
+    ///
+    /// As with `insert`, the first insertion of a parent is kept and later
+    /// duplicates (and their payloads) are ignored.
+    pub fn insert_named(&mut self, parent: &str, payload: T) {
+        self.insert_named_stranded(parent, payload, StrandPolicy::Forward);
     }
-    pub fn get_parent(&self, seq: &str) -> Option<&SeqWrapper> {
-        if let Some(p) = self.parents.get(seq) {
-            Some(p)
+
+    /// Inserts a parent sequence along with its payload indexing the
+    /// strands selected by the policy.
+    pub fn insert_named_stranded(&mut self, parent: &str, payload: T, policy: StrandPolicy) {
+        if self.record_duplicate(parent) {
+            return;
+        }
+        self.insert_stranded(parent, policy);
+        if let Some(&id) = self.ids.get(parent) {
+            self.records[id].payload = Some(payload);
+        }
+    }
+
+    /// Resolves a sequence to the id of its parent and the strand of the
+    /// parent it matched
+    fn resolve(&self, seq: &str) -> Option<(usize, Strand)> {
+        if let Some(&id) = self.ids.get(seq) {
+            if self.records[id].policy.forward() {
+                return Some((id, Strand::Forward));
+            }
+            // a palindrome is its own reverse complement
+            if is_palindrome(seq.as_bytes()) {
+                return Some((id, Strand::Reverse));
+            }
+        }
+        let key = self.key(seq);
+        // canonical queries stored as their reverse complement
+        let flipped = key.as_ref() != seq;
+        if flipped {
+            if let Some(&id) = self.ids.get(key.as_ref()) {
+                return Some((id, Strand::Reverse));
+            }
+        }
+        let alias = self.unambiguous.get(key.as_ref())?;
+        let strand = if flipped {
+            alias.strand.flip()
         } else {
-            self.unambiguous.get(seq)
+            alias.strand
+        };
+        Some((alias.id, strand))
+    }
+
+    pub fn get_parent(&self, seq: &str) -> Option<&SeqWrapper> {
+        self.resolve(seq).map(|(id, _)| &self.records[id].parent)
+    }
+    /// Classifies how a sequence relates to the parents of the index
+    pub fn assign(&self, seq: &str) -> Assignment {
+        match self.get_parent(seq) {
+            Some(p) if p.sequence() == seq => Assignment::Exact(p.clone()),
+            Some(p) => Assignment::Corrected(p.clone()),
+            None if self.candidates(seq).is_some() => Assignment::Ambiguous,
+            None => Assignment::Unassigned,
+        }
+    }
+    /// Retrieves the parent of a sequence, resolving ambiguous sequences
+    /// to their most likely parent using observed parent abundances.
+    ///
+    /// Base qualities (phred+33) are optional and used to weight each
+    /// candidate by the probability of a sequencing error at the position
+    /// it differs from the query.
+    pub fn get_parent_with_prior(
+        &self,
+        seq: &str,
+        qual: Option<&[u8]>,
+        prior: &AbundancePrior,
+    ) -> Option<&SeqWrapper> {
+        if let Some(p) = self.get_parent(seq) {
+            return Some(p);
         }
+        self.candidates
+            .get(self.key(seq).as_ref())
+            .and_then(|candidates| prior.resolve(seq, qual, candidates))
+    }
+    /// Returns the candidate parents of an ambiguous sequence
+    pub fn candidates(&self, seq: &str) -> Option<&[SeqWrapper]> {
+        self.candidates
+            .get(self.key(seq).as_ref())
+            .map(|c| c.as_slice())
+    }
+    /// Retrieves the parent of a sequence and the strand it was matched on
+    pub fn get_parent_with_strand(&self, seq: &str) -> Option<(&SeqWrapper, Strand)> {
+        self.resolve(seq)
+            .map(|(id, strand)| (&self.records[id].parent, strand))
+    }
+    /// Retrieves the payload of the parent of a sequence
+    pub fn get_named(&self, seq: &str) -> Option<&T> {
+        self.resolve(seq)
+            .and_then(|(id, _)| self.records[id].payload.as_ref())
+    }
+    /// Returns the payload of a parent sequence
+    pub fn payload(&self, parent: &str) -> Option<&T> {
+        self.ids
+            .get(parent)
+            .and_then(|&id| self.records[id].payload.as_ref())
     }
     pub fn parents(&self) -> &HashSet<SeqWrapper> {
         &self.parents
     }
+    /// Returns the distinct parent lengths in ascending order
+    pub fn lengths(&self) -> &[usize] {
+        &self.lengths
+    }
+    /// Returns the palindromic parents found in canonical mode
+    pub fn palindromes(&self) -> &HashSet<SeqWrapper> {
+        &self.palindromes
+    }
     pub fn ambiguous(&self) -> &HashSet<SeqWrapper> {
         &self.ambiguous
     }
-    pub fn unambiguous(&self) -> &HashMap<SeqWrapper, SeqWrapper> {
+    /// Returns every unambiguous alias with its parent and strand. Before
+    /// 0.3 this mapped aliases directly to their parent sequence; use
+    /// `alias.parent` for the same value.
+    pub fn unambiguous(&self) -> &HashMap<SeqWrapper, Alias> {
         &self.unambiguous
     }
+    /// Returns the parents that were inserted more than once along with
+    /// the number of ignored repeats
+    pub fn duplicates(&self) -> &HashMap<SeqWrapper, usize> {
+        &self.duplicates
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod testing {
     use super::Disambiseq;
+    use crate::{
+        prior::AbundancePrior,
+        strand::{Strand, StrandPolicy},
+        two_pass::Assignment,
+    };
 
     #[test]
     fn init_slice() {
@@ -182,4 +509,226 @@ mod testing {
         assert_eq!(dsq.get_parent("AGTAA").unwrap().sequence(), "AGTAA");
         assert_eq!(dsq.get_parent("ATAGT").unwrap().sequence(), "ACTAA");
     }
+
+    #[test]
+    fn ambiguous_candidates() {
+        let sequences = vec!["ACT".to_string(), "AGT".to_string()];
+        let das = Disambiseq::from_slice(&sequences);
+        let candidates = das.candidates("ATT").unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].sequence(), "ACT");
+        assert_eq!(candidates[1].sequence(), "AGT");
+        assert!(das.candidates("TCT").is_none());
+    }
+
+    #[test]
+    fn prior_get() {
+        let sequences = vec!["ACT".to_string(), "AGT".to_string()];
+        let das = Disambiseq::from_slice(&sequences);
+        let mut prior = AbundancePrior::new();
+        prior.add("ACT", 1000);
+        prior.add("AGT", 2);
+        assert_eq!(
+            das.get_parent_with_prior("ATT", None, &prior)
+                .unwrap()
+                .sequence(),
+            "ACT"
+        );
+        assert_eq!(
+            das.get_parent_with_prior("TCT", None, &prior)
+                .unwrap()
+                .sequence(),
+            "ACT"
+        );
+    }
+
+    #[test]
+    fn prior_get_below_threshold() {
+        let sequences = vec!["ACT".to_string(), "AGT".to_string()];
+        let das = Disambiseq::from_slice(&sequences);
+        let mut prior = AbundancePrior::new();
+        prior.add("ACT", 10);
+        prior.add("AGT", 8);
+        assert_eq!(das.get_parent_with_prior("ATT", None, &prior), None);
+    }
+
+    #[test]
+    fn strand_get() {
+        let mut dsq = Disambiseq::new();
+        dsq.insert_with_reverse_complement("ACTAA");
+        let (parent, strand) = dsq.get_parent_with_strand("ACTAA").unwrap();
+        assert_eq!((parent.sequence(), strand), ("ACTAA", Strand::Forward));
+        let (parent, strand) = dsq.get_parent_with_strand("ACTAT").unwrap();
+        assert_eq!((parent.sequence(), strand), ("ACTAA", Strand::Forward));
+        let (parent, strand) = dsq.get_parent_with_strand("TTAGT").unwrap();
+        assert_eq!((parent.sequence(), strand), ("ACTAA", Strand::Reverse));
+        let (parent, strand) = dsq.get_parent_with_strand("TTAGA").unwrap();
+        assert_eq!((parent.sequence(), strand), ("ACTAA", Strand::Reverse));
+    }
+
+    #[test]
+    fn strand_policy_reverse() {
+        let mut dsq = Disambiseq::new();
+        dsq.insert_stranded("ACTAA", StrandPolicy::Reverse);
+        assert_eq!(dsq.unambiguous().len(), 16);
+        assert_eq!(dsq.get_parent("ACTAT"), None);
+        assert_eq!(dsq.get_parent("TTAGA").unwrap().sequence(), "ACTAA");
+        assert_eq!(dsq.get_parent("TTAGT").unwrap().sequence(), "ACTAA");
+
+        // the forward parent itself is not indexed
+        assert_eq!(dsq.get_parent("ACTAA"), None);
+        assert_eq!(dsq.assign("ACTAA"), Assignment::Unassigned);
+        assert_eq!(dsq.get_parent_with_strand("ACTAA"), None);
+        assert!(dsq
+            .unambiguous()
+            .values()
+            .all(|alias| alias.strand == Strand::Reverse));
+        let (_, strand) = dsq.get_parent_with_strand("TTAGT").unwrap();
+        assert_eq!(strand, Strand::Reverse);
+    }
+
+    #[test]
+    fn strand_canonical() {
+        // TTAGT and its aliases are mostly stored as reverse complements
+        let mut dsq = Disambiseq::canonical();
+        dsq.insert("TTAGT");
+        for (seq, expected) in [
+            ("TTAGT", Strand::Forward),
+            ("TTAGA", Strand::Forward),
+            ("ACTAA", Strand::Reverse),
+            ("ACTAT", Strand::Reverse),
+        ] {
+            let (parent, strand) = dsq.get_parent_with_strand(seq).unwrap();
+            assert_eq!((parent.sequence(), strand), ("TTAGT", expected));
+        }
+        assert_eq!(dsq.unambiguous()["ACTAA"].strand, Strand::Reverse);
+        assert_eq!(dsq.unambiguous()["TCTAA"].strand, Strand::Reverse);
+    }
+
+    #[test]
+    fn strand_policy_parent_collision() {
+        // the reverse complement of the first parent is the second parent
+        let mut dsq = Disambiseq::new();
+        dsq.insert_with_reverse_complement("AACC");
+        dsq.insert_with_reverse_complement("GGTT");
+        assert_eq!(dsq.get_parent("AACC").unwrap().sequence(), "AACC");
+        assert_eq!(dsq.get_parent("GGTT").unwrap().sequence(), "GGTT");
+
+        // palindromic parents only index their aliases once
+        let mut dsq = Disambiseq::new();
+        dsq.insert_with_reverse_complement("ACGT");
+        assert!(dsq.ambiguous().is_empty());
+        assert_eq!(dsq.unambiguous().len(), 12);
+        assert!(!dsq.unambiguous().contains_key("ACGT"));
+    }
+
+    #[test]
+    fn canonical_get() {
+        let mut dsq = Disambiseq::canonical();
+        dsq.insert("ACTAA");
+        dsq.insert("AGTAA");
+        assert!(dsq.is_canonical());
+        assert_eq!(dsq.parents().len(), 2);
+        assert_eq!(dsq.ambiguous().len(), 2);
+        assert_eq!(dsq.unambiguous().len(), 24);
+        assert_eq!(dsq.get_parent("ACTAA").unwrap().sequence(), "ACTAA");
+        assert_eq!(dsq.get_parent("TTAGT").unwrap().sequence(), "ACTAA");
+        assert_eq!(dsq.get_parent("TTACT").unwrap().sequence(), "AGTAA");
+        assert_eq!(dsq.get_parent("ATAGT").unwrap().sequence(), "ACTAA");
+        assert_eq!(dsq.get_parent("ACTAT").unwrap().sequence(), "ACTAA");
+        assert_eq!(dsq.get_parent("ATTAA"), None);
+        assert_eq!(dsq.get_parent("TTAAT"), None);
+        assert_eq!(dsq.candidates("TTAAT").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn canonical_palindrome() {
+        let mut dsq = Disambiseq::canonical();
+        dsq.insert("ACGT");
+        dsq.insert("ACTAA");
+        assert_eq!(dsq.palindromes().len(), 1);
+        assert!(dsq.palindromes().contains("ACGT"));
+        assert!(dsq.ambiguous().is_empty());
+        assert_eq!(dsq.get_parent("ACGT").unwrap().sequence(), "ACGT");
+        assert_eq!(dsq.get_parent("TCGT").unwrap().sequence(), "ACGT");
+        assert_eq!(dsq.get_parent("ACGA").unwrap().sequence(), "ACGT");
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Guide {
+        id: &'static str,
+        gene: &'static str,
+    }
+
+    #[test]
+    fn named_get() {
+        let mut dsq = Disambiseq::named();
+        dsq.insert_named(
+            "ACT",
+            Guide {
+                id: "g1",
+                gene: "TP53",
+            },
+        );
+        dsq.insert_named(
+            "AGT",
+            Guide {
+                id: "g2",
+                gene: "KRAS",
+            },
+        );
+        assert_eq!(dsq.get_named("ACT").unwrap().id, "g1");
+        assert_eq!(dsq.get_named("TCT").unwrap().gene, "TP53");
+        assert_eq!(dsq.get_named("AGG").unwrap().id, "g2");
+        assert_eq!(dsq.get_named("ATT"), None);
+        assert_eq!(dsq.payload("AGT").unwrap().gene, "KRAS");
+    }
+
+    #[test]
+    fn named_duplicate() {
+        let mut dsq = Disambiseq::named();
+        dsq.insert_named("ACT", "first");
+        dsq.insert_named("ACT", "second");
+        dsq.insert("AGT");
+        assert_eq!(dsq.parents().len(), 2);
+        assert_eq!(dsq.get_named("ACT"), Some(&"first"));
+        assert_eq!(dsq.get_named("AGT"), None);
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut dsq = Disambiseq::new();
+        dsq.insert("ACT");
+        dsq.insert("ACT");
+        dsq.insert_with_reverse_complement("ACT");
+        dsq.insert("AGT");
+        assert_eq!(dsq.parents().len(), 2);
+        assert_eq!(dsq.duplicates().len(), 1);
+        assert_eq!(dsq.duplicates()["ACT"], 2);
+    }
+
+    #[test]
+    fn named_canonical_get() {
+        let mut dsq = Disambiseq::named_canonical();
+        dsq.insert_named("ACTAA", 1);
+        dsq.insert_named("AGTAA", 2);
+        assert_eq!(dsq.get_named("TTAGT"), Some(&1));
+        assert_eq!(dsq.get_named("TTACA"), Some(&2));
+    }
+
+    #[test]
+    fn assign() {
+        let sequences = vec!["ACT".to_string(), "AGT".to_string()];
+        let dsq = Disambiseq::from_slice(&sequences);
+        assert_eq!(
+            dsq.assign("ACT"),
+            Assignment::Exact(dsq.get_parent("ACT").unwrap().clone())
+        );
+        assert_eq!(
+            dsq.assign("TCT"),
+            Assignment::Corrected(dsq.get_parent("ACT").unwrap().clone())
+        );
+        assert_eq!(dsq.assign("ATT"), Assignment::Ambiguous);
+        assert_eq!(dsq.assign("GGG"), Assignment::Unassigned);
+    }
 }
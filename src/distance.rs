@@ -0,0 +1,346 @@
+use crate::{
+    error::Result,
+    utils::{hamming_distance, levenshtein_distance},
+    Disambiseq,
+};
+use hashbrown::{HashMap, HashSet};
+use std::io::Write;
+
+/// The distance measured between two sequences
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Substitutions only
+    #[default]
+    Hamming,
+    /// Substitutions, insertions and deletions
+    Levenshtein,
+}
+impl DistanceMetric {
+    /// Returns the distance between two sequences. Hamming distances
+    /// between sequences of different lengths are the longer length.
+    pub fn distance(&self, a: &[u8], b: &[u8]) -> usize {
+        match self {
+            Self::Hamming => hamming_distance(a, b).unwrap_or(a.len().max(b.len())),
+            Self::Levenshtein => levenshtein_distance(a, b),
+        }
+    }
+}
+
+/// A full pairwise distance matrix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistanceMatrix {
+    /// The sequences of the rows and columns
+    pub labels: Vec<String>,
+    /// Row-major `n * n` distances
+    distances: Vec<usize>,
+}
+impl DistanceMatrix {
+    /// Returns the distance between the `i`-th and `j`-th sequences
+    pub fn get(&self, i: usize, j: usize) -> usize {
+        self.distances[i * self.labels.len() + j]
+    }
+
+    /// Returns the number of sequences
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns true if the matrix has no sequences
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Writes the matrix as TSV with a header row and one row per sequence
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        for label in &self.labels {
+            write!(writer, "\t{label}")?;
+        }
+        writeln!(writer)?;
+        for (i, label) in self.labels.iter().enumerate() {
+            write!(writer, "{label}")?;
+            for j in 0..self.len() {
+                write!(writer, "\t{}", self.get(i, j))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pairwise distances between parent sequences.
+///
+/// Works directly on the sequences so large libraries can be checked
+/// without building their alias maps. Hamming distances between
+/// sequences of different lengths are the length of the longer one.
+#[derive(Debug, Clone)]
+pub struct PairwiseDistances {
+    sequences: Vec<String>,
+    metric: DistanceMetric,
+}
+impl PairwiseDistances {
+    pub fn new<S: AsRef<str>>(sequences: &[S]) -> Self {
+        Self {
+            sequences: sequences.iter().map(|s| s.as_ref().to_string()).collect(),
+            metric: DistanceMetric::Hamming,
+        }
+    }
+
+    /// Sets the distance metric (Hamming by default)
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn sequences(&self) -> &[String] {
+        &self.sequences
+    }
+
+    fn distance(&self, i: usize, j: usize) -> usize {
+        self.metric
+            .distance(self.sequences[i].as_bytes(), self.sequences[j].as_bytes())
+    }
+
+    /// Computes the full distance matrix
+    pub fn matrix(&self) -> DistanceMatrix {
+        let n = self.sequences.len();
+        let mut distances = vec![0; n * n];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let d = self.distance(i, j);
+                distances[i * n + j] = d;
+                distances[j * n + i] = d;
+            }
+        }
+        DistanceMatrix {
+            labels: self.sequences.clone(),
+            distances,
+        }
+    }
+
+    /// Counts the pairs of sequences at each distance (the index of the
+    /// returned vector) without storing the matrix
+    pub fn histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for i in 0..self.sequences.len() {
+            for j in (i + 1)..self.sequences.len() {
+                let d = self.distance(i, j);
+                if histogram.len() <= d {
+                    histogram.resize(d + 1, 0);
+                }
+                histogram[d] += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Returns the first pair `(i, j, distance)` with `i < j` at the
+    /// minimum distance without storing the matrix
+    pub fn closest(&self) -> Option<(usize, usize, usize)> {
+        let mut closest: Option<(usize, usize, usize)> = None;
+        for i in 0..self.sequences.len() {
+            for j in (i + 1)..self.sequences.len() {
+                let d = self.distance(i, j);
+                if closest.is_none_or(|(_, _, min)| d < min) {
+                    closest = Some((i, j, d));
+                }
+            }
+        }
+        closest
+    }
+
+    /// Returns every pair `(i, j, distance)` with `i < j` whose distance is
+    /// at most `max_distance`, sorted by index.
+    ///
+    /// For the Hamming metric each sequence is split into
+    /// `max_distance + 1` blocks: two sequences within the distance must
+    /// share at least one block exactly, so only pairs sharing a block are
+    /// compared, and pairs of different lengths are included once the
+    /// distance reaches the longer length. Levenshtein distances are
+    /// compared for every pair whose lengths are close enough.
+    pub fn within(&self, max_distance: usize) -> Vec<(usize, usize, usize)> {
+        let mut pairs = match self.metric {
+            DistanceMetric::Hamming => self.hamming_within(max_distance),
+            DistanceMetric::Levenshtein => self.levenshtein_within(max_distance),
+        };
+        pairs.sort_unstable();
+        pairs
+    }
+
+    fn hamming_within(&self, max_distance: usize) -> Vec<(usize, usize, usize)> {
+        let mut by_length: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (idx, seq) in self.sequences.iter().enumerate() {
+            by_length.entry(seq.len()).or_default().push(idx);
+        }
+
+        // sequences of different lengths are as far apart as the longer one
+        let mut pairs = Vec::new();
+        for (a, group_a) in &by_length {
+            for (b, group_b) in &by_length {
+                if a < b && max_distance >= *b {
+                    for &i in group_a {
+                        pairs.extend(group_b.iter().map(|&j| (i.min(j), i.max(j), *b)));
+                    }
+                }
+            }
+        }
+
+        for (length, group) in by_length {
+            if max_distance >= length {
+                // every pair of equal length is within the distance
+                for (k, &i) in group.iter().enumerate() {
+                    pairs.extend(group[k + 1..].iter().map(|&j| (i, j, self.distance(i, j))));
+                }
+                continue;
+            }
+            let blocks = max_distance + 1;
+            let mut candidates: HashSet<(usize, usize)> = HashSet::new();
+            for block in 0..blocks {
+                let (start, end) = (block * length / blocks, (block + 1) * length / blocks);
+                let mut buckets: HashMap<&[u8], Vec<usize>> = HashMap::new();
+                for &idx in &group {
+                    buckets
+                        .entry(&self.sequences[idx].as_bytes()[start..end])
+                        .or_default()
+                        .push(idx);
+                }
+                for bucket in buckets.values() {
+                    for (k, &i) in bucket.iter().enumerate() {
+                        for &j in &bucket[k + 1..] {
+                            candidates.insert((i.min(j), i.max(j)));
+                        }
+                    }
+                }
+            }
+            pairs.extend(
+                candidates
+                    .into_iter()
+                    .map(|(i, j)| (i, j, self.distance(i, j)))
+                    .filter(|(_, _, d)| *d <= max_distance),
+            );
+        }
+        pairs
+    }
+
+    fn levenshtein_within(&self, max_distance: usize) -> Vec<(usize, usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..self.sequences.len() {
+            for j in (i + 1)..self.sequences.len() {
+                if self.sequences[i].len().abs_diff(self.sequences[j].len()) > max_distance {
+                    continue;
+                }
+                let d = self.distance(i, j);
+                if d <= max_distance {
+                    pairs.push((i, j, d));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+impl<T> Disambiseq<T> {
+    /// Returns the pairwise distances between the parents, sorted by
+    /// sequence
+    pub fn pairwise_distances(&self) -> PairwiseDistances {
+        let mut parents: Vec<&str> = self.parents().iter().map(|p| p.sequence()).collect();
+        parents.sort_unstable();
+        PairwiseDistances::new(&parents)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    const PARENTS: [&str; 4] = ["AAAA", "AAAT", "AATT", "GGGG"];
+
+    #[test]
+    fn matrix() {
+        let matrix = PairwiseDistances::new(&PARENTS).matrix();
+        assert_eq!(matrix.len(), 4);
+        assert_eq!(matrix.get(0, 1), 1);
+        assert_eq!(matrix.get(2, 0), 2);
+        assert_eq!(matrix.get(3, 3), 0);
+
+        let mut out = Vec::new();
+        PairwiseDistances::new(&PARENTS[..2])
+            .matrix()
+            .write(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\tAAAA\tAAAT\nAAAA\t0\t1\nAAAT\t1\t0\n"
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let histogram = PairwiseDistances::new(&PARENTS).histogram();
+        assert_eq!(histogram, vec![0, 2, 1, 0, 3]);
+        assert_eq!(histogram.iter().sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn within() {
+        let distances = PairwiseDistances::new(&PARENTS);
+        assert_eq!(distances.within(0), vec![]);
+        assert_eq!(distances.within(1), vec![(0, 1, 1), (1, 2, 1)]);
+        assert_eq!(distances.within(2), vec![(0, 1, 1), (0, 2, 2), (1, 2, 1)]);
+
+        // the blocked search agrees with the full matrix
+        let matrix = distances.matrix();
+        for d in 0..=4 {
+            let expected: Vec<_> = (0..4)
+                .flat_map(|i| ((i + 1)..4).map(move |j| (i, j)))
+                .map(|(i, j)| (i, j, matrix.get(i, j)))
+                .filter(|(_, _, dist)| *dist <= d)
+                .collect();
+            assert_eq!(distances.within(d), expected);
+        }
+    }
+
+    #[test]
+    fn within_mixed_lengths() {
+        let sequences = ["AC", "AG", "ACG", "ACT", "TTTT", "ÄCT"];
+        let distances = PairwiseDistances::new(&sequences);
+        let matrix = distances.matrix();
+        let n = sequences.len();
+        for d in 0..=5 {
+            let expected: Vec<_> = (0..n)
+                .flat_map(|i| ((i + 1)..n).map(move |j| (i, j)))
+                .map(|(i, j)| (i, j, matrix.get(i, j)))
+                .filter(|(_, _, dist)| *dist <= d)
+                .collect();
+            assert_eq!(distances.within(d), expected);
+        }
+        assert!(distances.within(3).contains(&(0, 2, 3)));
+    }
+
+    #[test]
+    fn closest() {
+        assert_eq!(PairwiseDistances::new(&PARENTS).closest(), Some((0, 1, 1)));
+        assert_eq!(
+            PairwiseDistances::new(&["AAAA", "CCCCC"]).closest(),
+            Some((0, 1, 5))
+        );
+        assert_eq!(PairwiseDistances::new(&["AAAA"]).closest(), None);
+    }
+
+    #[test]
+    fn levenshtein() {
+        let distances = PairwiseDistances::new(&["ACGT", "CGTA", "ACG"])
+            .with_metric(DistanceMetric::Levenshtein);
+        assert_eq!(distances.within(1), vec![(0, 2, 1)]);
+        assert_eq!(distances.matrix().get(0, 1), 2);
+        let hamming = PairwiseDistances::new(&["ACGT", "ACG"]).matrix();
+        assert_eq!(hamming.get(0, 1), 4);
+    }
+
+    #[test]
+    fn from_index() {
+        let dsq = Disambiseq::from_slice(&["GGGG".to_string(), "AAAA".to_string()]);
+        let distances = dsq.pairwise_distances();
+        assert_eq!(distances.sequences(), &["AAAA", "GGGG"]);
+        assert_eq!(distances.histogram(), vec![0, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{fastq::FastqRecord, two_pass::Assignment, Disambiseq, SeqWrapper};
+use hashbrown::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/// The outcome of assigning a pair of i7/i5 index reads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DualAssignment {
+    /// Both indices resolved to a valid sample pair
+    Sample {
+        sample: String,
+        i7: SeqWrapper,
+        i5: SeqWrapper,
+    },
+    /// Both indices resolved but do not form a valid sample pair
+    IndexHop { i7: SeqWrapper, i5: SeqWrapper },
+    /// At least one index could not be resolved
+    Unresolved { i7: Assignment, i5: Assignment },
+}
+
+/// Combinatorial i7/i5 dual-index library with index-hopping detection.
+///
+/// Each index is corrected individually and the corrected pair is then
+/// checked against the sample table.
+#[derive(Debug, Clone, Default)]
+pub struct DualIndex {
+    i7: Disambiseq,
+    i5: Disambiseq,
+    /// Samples keyed by their i7 and then their i5 index
+    samples: HashMap<String, HashMap<String, String>>,
+}
+impl DualIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample with its i7 and i5 index sequences. A repeated pair
+    /// keeps its first sample.
+    pub fn add_sample(&mut self, sample: &str, i7: &str, i5: &str) {
+        self.i7.insert(i7);
+        self.i5.insert(i5);
+        self.samples
+            .entry_ref(i7)
+            .or_default()
+            .entry_ref(i5)
+            .or_insert_with(|| sample.to_string());
+    }
+
+    /// Builds a library from `(sample, i7, i5)` rows
+    pub fn from_samples<S: AsRef<str>>(samples: &[(S, S, S)]) -> Self {
+        let mut dual = Self::new();
+        samples
+            .iter()
+            .for_each(|(s, i7, i5)| dual.add_sample(s.as_ref(), i7.as_ref(), i5.as_ref()));
+        dual
+    }
+
+    pub fn i7(&self) -> &Disambiseq {
+        &self.i7
+    }
+    pub fn i5(&self) -> &Disambiseq {
+        &self.i5
+    }
+
+    /// Returns the sample of a valid i7/i5 pair of parent sequences
+    pub fn sample(&self, i7: &str, i5: &str) -> Option<&str> {
+        self.samples
+            .get(i7)
+            .and_then(|i5s| i5s.get(i5))
+            .map(|s| s.as_str())
+    }
+
+    /// Returns the number of samples
+    pub fn len(&self) -> usize {
+        self.samples.values().map(|i5s| i5s.len()).sum()
+    }
+
+    /// Returns true if no samples were added
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Assigns a pair of index reads to a sample
+    pub fn assign(&self, i7: &str, i5: &str) -> DualAssignment {
+        let i7 = self.i7.assign(i7);
+        let i5 = self.i5.assign(i5);
+        match (i7.parent(), i5.parent()) {
+            (Some(p7), Some(p5)) => match self.sample(p7.sequence(), p5.sequence()) {
+                Some(sample) => DualAssignment::Sample {
+                    sample: sample.to_string(),
+                    i7: p7.clone(),
+                    i5: p5.clone(),
+                },
+                None => DualAssignment::IndexHop {
+                    i7: p7.clone(),
+                    i5: p5.clone(),
+                },
+            },
+            _ => DualAssignment::Unresolved { i7, i5 },
+        }
+    }
+
+    /// Assigns a record using the `i7+i5` indices at the end of an
+    /// Illumina header (e.g. `1:N:0:ACGTACGT+TTGGCCAA`). Returns `None` if
+    /// the header carries no dual index.
+    pub fn assign_record(&self, record: &FastqRecord) -> Option<DualAssignment> {
+        let (i7, i5) = header_indices(&record.name)?;
+        Some(self.assign(i7, i5))
+    }
+}
+
+/// Parses the `i7+i5` indices from the last field of an Illumina header
+pub fn header_indices(name: &str) -> Option<(&str, &str)> {
+    let comment = name.split_whitespace().nth(1)?;
+    comment.rsplit(':').next()?.split_once('+')
+}
+
+/// Counts of dual-index assignments and the pair-level hopping matrix
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DualIndexSummary {
+    /// Number of reads assigned to each sample
+    pub samples: HashMap<String, usize>,
+    /// Number of index-hopping reads
+    pub hopped: usize,
+    /// Number of reads with an unresolved index
+    pub unresolved: usize,
+    /// Number of reads observed for every resolved i7/i5 pair
+    pub pairs: HashMap<(SeqWrapper, SeqWrapper), usize>,
+}
+impl DualIndexSummary {
+    /// Records a single assignment
+    pub fn add(&mut self, assignment: &DualAssignment) {
+        match assignment {
+            DualAssignment::Sample { sample, i7, i5 } => {
+                *self.samples.entry_ref(sample.as_str()).or_default() += 1;
+                self.add_pair(i7, i5);
+            }
+            DualAssignment::IndexHop { i7, i5 } => {
+                self.hopped += 1;
+                self.add_pair(i7, i5);
+            }
+            DualAssignment::Unresolved { .. } => self.unresolved += 1,
+        }
+    }
+
+    fn add_pair(&mut self, i7: &SeqWrapper, i5: &SeqWrapper) {
+        *self.pairs.entry((i7.clone(), i5.clone())).or_default() += 1;
+    }
+
+    /// Returns the total number of reads recorded
+    pub fn total(&self) -> usize {
+        self.samples.values().sum::<usize>() + self.hopped + self.unresolved
+    }
+
+    /// Returns the fraction of resolved pairs which are index hops
+    pub fn hopping_rate(&self) -> f64 {
+        let resolved = self.total() - self.unresolved;
+        if resolved == 0 {
+            0.0
+        } else {
+            self.hopped as f64 / resolved as f64
+        }
+    }
+
+    /// Writes the pair-level matrix as TSV with one row per i7 and one
+    /// column per i5, both sorted
+    pub fn write_matrix<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let counts: HashMap<(&str, &str), usize> = self
+            .pairs
+            .iter()
+            .map(|((i7, i5), count)| ((i7.sequence(), i5.sequence()), *count))
+            .collect();
+        let mut i7s: Vec<&str> = counts
+            .keys()
+            .map(|(i7, _)| *i7)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let mut i5s: Vec<&str> = counts
+            .keys()
+            .map(|(_, i5)| *i5)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        i7s.sort_unstable();
+        i5s.sort_unstable();
+
+        write!(writer, "i7")?;
+        for i5 in &i5s {
+            write!(writer, "\t{i5}")?;
+        }
+        writeln!(writer)?;
+        for i7 in i7s {
+            write!(writer, "{i7}")?;
+            for i5 in &i5s {
+                let count = counts.get(&(i7, *i5)).unwrap_or(&0);
+                write!(writer, "\t{count}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn library() -> DualIndex {
+        DualIndex::from_samples(&[("s1", "AAAA", "CCCC"), ("s2", "GGGG", "TTTT")])
+    }
+
+    #[test]
+    fn assign_sample() {
+        let dual = library();
+        assert_eq!(dual.len(), 2);
+        match dual.assign("AAAT", "CCCC") {
+            DualAssignment::Sample { sample, .. } => assert_eq!(sample, "s1"),
+            other => panic!("unexpected assignment: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn assign_hop() {
+        let dual = library();
+        match dual.assign("AAAA", "TTTA") {
+            DualAssignment::IndexHop { i7, i5 } => {
+                assert_eq!(i7.sequence(), "AAAA");
+                assert_eq!(i5.sequence(), "TTTT");
+            }
+            other => panic!("unexpected assignment: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn assign_unresolved() {
+        let dual = library();
+        match dual.assign("ACAC", "CCCC") {
+            DualAssignment::Unresolved { i7, i5 } => {
+                assert_eq!(i7, Assignment::Unassigned);
+                assert!(i5.parent().is_some());
+            }
+            other => panic!("unexpected assignment: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn assign_record() {
+        let dual = library();
+        let record = FastqRecord {
+            name: "r1 1:N:0:GGGG+TTTT".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            dual.assign_record(&record),
+            Some(DualAssignment::Sample { .. })
+        ));
+        let record = FastqRecord {
+            name: "r1".to_string(),
+            ..Default::default()
+        };
+        assert!(dual.assign_record(&record).is_none());
+    }
+
+    #[test]
+    fn summary_matrix() {
+        let dual = library();
+        let mut summary = DualIndexSummary::default();
+        for (i7, i5) in [
+            ("AAAA", "CCCC"),
+            ("AAAA", "CCCC"),
+            ("GGGG", "TTTT"),
+            ("AAAA", "TTTT"),
+            ("ACAC", "TTTT"),
+        ] {
+            summary.add(&dual.assign(i7, i5));
+        }
+        assert_eq!(summary.samples["s1"], 2);
+        assert_eq!(summary.samples["s2"], 1);
+        assert_eq!(summary.hopped, 1);
+        assert_eq!(summary.unresolved, 1);
+        assert_eq!(summary.total(), 5);
+        assert!((summary.hopping_rate() - 0.25).abs() < 1e-9);
+
+        let mut out = Vec::new();
+        summary.write_matrix(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "i7\tCCCC\tTTTT\nAAAA\t2\t1\nGGGG\t0\t1\n"
+        );
+    }
+}
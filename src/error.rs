@@ -0,0 +1,73 @@
+use std::fmt;
+
+/// Errors raised while loading libraries or processing reads
+#[derive(Debug)]
+pub enum Error {
+    /// An underlying I/O error
+    Io(std::io::Error),
+    /// A malformed row or record at the given (1-based) line
+    Malformed { line: usize, message: String },
+    /// A required section is missing from the input
+    MissingSection { section: String },
+    /// An invalid read structure string
+    InvalidReadStructure { structure: String, message: String },
+    /// Mates of a multi-file read disagree at the given (1-based) record
+    Desynchronized { record: usize, message: String },
+    /// Fewer barcodes than requested satisfy the design constraints
+    DesignExhausted { requested: usize, designed: usize },
+    /// An output name that is not a plain file name or is reserved
+    InvalidOutputName { name: String, message: String },
+    /// A barcode round that is empty or mixes barcode lengths
+    InvalidRound { round: usize, message: String },
+}
+impl Error {
+    pub(crate) fn malformed(line: usize, message: impl Into<String>) -> Self {
+        Self::Malformed {
+            line,
+            message: message.into(),
+        }
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Malformed { line, message } => write!(f, "line {line}: {message}"),
+            Self::MissingSection { section } => write!(f, "missing {section} section"),
+            Self::InvalidReadStructure { structure, message } => {
+                write!(f, "invalid read structure '{structure}': {message}")
+            }
+            Self::Desynchronized { record, message } => {
+                write!(f, "mates desynchronized at record {record}: {message}")
+            }
+            Self::DesignExhausted {
+                requested,
+                designed,
+            } => write!(
+                f,
+                "only {designed} of {requested} barcodes satisfy the design constraints"
+            ),
+            Self::InvalidOutputName { name, message } => {
+                write!(f, "invalid output name '{name}': {message}")
+            }
+            Self::InvalidRound { round, message } => {
+                write!(f, "invalid barcode round {round}: {message}")
+            }
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,427 @@
+use crate::{
+    error::{Error, Result},
+    read_structure::{MultiReadStructure, StructuredRead},
+    two_pass::Assignment,
+    Disambiseq,
+};
+use std::io::{self, BufRead, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+
+/// A single FASTQ record
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FastqRecord {
+    /// The header without the leading `@`
+    pub name: String,
+    pub seq: Vec<u8>,
+    pub qual: Vec<u8>,
+}
+impl FastqRecord {
+    /// Returns the read id: the header up to the first whitespace
+    pub fn id(&self) -> &str {
+        self.name.split_whitespace().next().unwrap_or_default()
+    }
+
+    /// Writes the record in FASTQ format
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(b"@")?;
+        writer.write_all(self.name.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer.write_all(&self.seq)?;
+        writer.write_all(b"\n+\n")?;
+        writer.write_all(&self.qual)?;
+        writer.write_all(b"\n")
+    }
+}
+
+/// Streams records from a FASTQ file one at a time
+#[derive(Debug)]
+pub struct FastqReader<R> {
+    reader: R,
+    line: usize,
+    buffer: String,
+}
+impl<R: BufRead> FastqReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: 0,
+            buffer: String::new(),
+        }
+    }
+
+    /// Reads the next line into the buffer returning false at EOF
+    fn next_line(&mut self) -> Result<bool> {
+        self.buffer.clear();
+        let n = self.reader.read_line(&mut self.buffer)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        let trimmed = self.buffer.trim_end_matches(['\n', '\r']).len();
+        self.buffer.truncate(trimmed);
+        Ok(true)
+    }
+
+    /// Reads a line which must be present within a record
+    fn expect_line(&mut self, what: &str) -> Result<()> {
+        if self.next_line()? {
+            Ok(())
+        } else {
+            Err(Error::malformed(
+                self.line + 1,
+                format!("truncated record: missing {what}"),
+            ))
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<FastqRecord>> {
+        // skip blank lines between records
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            if !self.buffer.is_empty() {
+                break;
+            }
+        }
+        let Some(name) = self.buffer.strip_prefix('@') else {
+            return Err(Error::malformed(self.line, "expected '@' header"));
+        };
+        let name = name.to_string();
+
+        self.expect_line("sequence")?;
+        let seq = self.buffer.as_bytes().to_vec();
+
+        self.expect_line("separator")?;
+        if !self.buffer.starts_with('+') {
+            return Err(Error::malformed(self.line, "expected '+' separator"));
+        }
+
+        self.expect_line("quality")?;
+        let qual = self.buffer.as_bytes().to_vec();
+        if qual.len() != seq.len() {
+            return Err(Error::malformed(
+                self.line,
+                format!(
+                    "quality length ({}) does not match sequence length ({})",
+                    qual.len(),
+                    seq.len()
+                ),
+            ));
+        }
+        Ok(Some(FastqRecord { name, seq, qual }))
+    }
+}
+impl<R: BufRead> Iterator for FastqReader<R> {
+    type Item = Result<FastqRecord>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Returns the id of a mate without a trailing `/1`-style mate number
+fn mate_id(record: &FastqRecord) -> &str {
+    let id = record.id();
+    match id.rsplit_once('/') {
+        Some((base, mate)) if !mate.is_empty() && mate.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => id,
+    }
+}
+
+/// Checks that the next records of every mate file are present and share
+/// the same read id
+fn synchronize(record: usize, mates: Vec<Option<FastqRecord>>) -> Result<Option<Vec<FastqRecord>>> {
+    let desync = |message: String| Error::Desynchronized { record, message };
+    if mates.iter().all(|m| m.is_none()) {
+        return Ok(None);
+    }
+    if let Some(missing) = mates.iter().position(|m| m.is_none()) {
+        return Err(desync(format!("mate {} ended early", missing + 1)));
+    }
+    let mates: Vec<FastqRecord> = mates.into_iter().flatten().collect();
+    let id = mate_id(&mates[0]);
+    if let Some(other) = mates.iter().skip(1).find(|m| mate_id(m) != id) {
+        return Err(desync(format!(
+            "mate names differ: '{}' and '{}'",
+            mates[0].id(),
+            other.id()
+        )));
+    }
+    Ok(Some(mates))
+}
+
+/// Streams the records of several mate files (e.g. R1, R2, I1, I2) in
+/// lockstep.
+///
+/// Every step yields one record per file. Files ending at different
+/// records or mates with different read ids raise
+/// [`Error::Desynchronized`] instead of silently drifting apart.
+#[derive(Debug)]
+pub struct MateReader<R> {
+    readers: Vec<FastqReader<R>>,
+    record: usize,
+}
+impl<R: BufRead> MateReader<R> {
+    pub fn new(readers: Vec<R>) -> Self {
+        Self {
+            readers: readers.into_iter().map(FastqReader::new).collect(),
+            record: 0,
+        }
+    }
+
+    fn read_mates(&mut self) -> Result<Option<Vec<FastqRecord>>> {
+        let mates = self
+            .readers
+            .iter_mut()
+            .map(|r| r.next().transpose())
+            .collect::<Result<Vec<_>>>()?;
+        self.record += 1;
+        synchronize(self.record, mates)
+    }
+}
+impl<R: BufRead> Iterator for MateReader<R> {
+    type Item = Result<Vec<FastqRecord>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_mates().transpose()
+    }
+}
+
+/// The assignment of a single FASTQ record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssignedRead {
+    /// The read header
+    pub name: String,
+    /// The extracted barcode (empty if the read was too short)
+    pub barcode: String,
+    pub assignment: Assignment,
+}
+
+/// Extracts `length` bases at `offset` from a sequence if it is long enough
+pub(crate) fn extract(seq: &[u8], offset: usize, length: usize) -> Option<&str> {
+    seq.get(offset..offset.checked_add(length)?)
+        .and_then(|bc| std::str::from_utf8(bc).ok())
+}
+
+/// Streams FASTQ records and assigns the barcode at a fixed offset of
+/// each read to a parent of the index.
+///
+/// Records are processed one at a time so memory use does not depend on
+/// the size of the input. Reads shorter than `offset + length` are
+/// unassigned.
+pub fn assign_fastq<'a, R, T>(
+    reader: R,
+    index: &'a Disambiseq<T>,
+    offset: usize,
+    length: usize,
+) -> impl Iterator<Item = Result<AssignedRead>> + 'a
+where
+    R: BufRead + 'a,
+{
+    FastqReader::new(reader).map(move |record| {
+        let record = record?;
+        let (barcode, assignment) = match extract(&record.seq, offset, length) {
+            Some(barcode) => (barcode.to_string(), index.assign(barcode)),
+            None => (String::new(), Assignment::Unassigned),
+        };
+        Ok(AssignedRead {
+            name: record.name,
+            barcode,
+            assignment,
+        })
+    })
+}
+
+/// The assignment of the mates of a multi-file read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssignedMates {
+    /// The header of the first mate
+    pub name: String,
+    /// The extracted and assigned segments or `None` if a mate was too
+    /// short for its read structure
+    pub read: Option<StructuredRead>,
+}
+
+/// Streams synchronized mate files and assigns the barcode segments of a
+/// per-mate read structure (the n-th barcode segment uses `indices[n]`).
+///
+/// Fails up front if there is not one reader per structure or one index
+/// per barcode segment.
+pub fn assign_mates<'a, R, T>(
+    readers: Vec<R>,
+    structure: &'a MultiReadStructure,
+    indices: &'a [&'a Disambiseq<T>],
+) -> Result<impl Iterator<Item = Result<AssignedMates>> + 'a>
+where
+    R: BufRead + 'a,
+{
+    structure.check_mates(readers.len())?;
+    structure.check_indices(indices.len())?;
+    Ok(MateReader::new(readers).map(move |mates| {
+        let mates = mates?;
+        let read = structure.assign(&mates, indices)?;
+        Ok(AssignedMates {
+            name: mates[0].name.clone(),
+            read,
+        })
+    }))
+}
+
+/// Opens a (possibly gzipped) FASTQ file and assigns the barcode at a
+/// fixed offset of each read
+#[cfg(feature = "io")]
+pub fn assign_fastq_path<'a, P, T>(
+    path: P,
+    index: &'a Disambiseq<T>,
+    offset: usize,
+    length: usize,
+) -> Result<impl Iterator<Item = Result<AssignedRead>> + 'a>
+where
+    P: AsRef<Path>,
+{
+    Ok(assign_fastq(crate::io::open(path)?, index, offset, length))
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::two_pass::CorrectionSummary;
+
+    const FASTQ: &str = "@r1 1:N\nNNACTGG\n+\nIIIIIII\n\
+                         @r2\nNNTCTGG\n+\nIIIIIII\n\
+                         @r3\nNNATTGG\n+\nIIIIIII\n\
+                         @r4\nNNA\n+\nIII\n";
+
+    #[test]
+    fn read_records() {
+        let records: Vec<_> = FastqReader::new(FASTQ.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].name, "r1 1:N");
+        assert_eq!(records[0].id(), "r1");
+        assert_eq!(records[0].seq, b"NNACTGG");
+        assert_eq!(records[3].qual, b"III");
+    }
+
+    #[test]
+    fn write_record() {
+        let record = FastqReader::new(FASTQ.as_bytes()).next().unwrap().unwrap();
+        let mut out = Vec::new();
+        record.write(&mut out).unwrap();
+        assert_eq!(out, b"@r1 1:N\nNNACTGG\n+\nIIIIIII\n");
+    }
+
+    #[test]
+    fn read_malformed() {
+        let data = "@r1\nACT\n+\nIII\nr2\nACT\n+\nIII\n";
+        let err = FastqReader::new(data.as_bytes()).nth(1).unwrap();
+        match err {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 5),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let data = "@r1\nACT\n+\nII\n";
+        match FastqReader::new(data.as_bytes()).next().unwrap() {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 4),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let data = "@r1\nACT\n+\n";
+        match FastqReader::new(data.as_bytes()).next().unwrap() {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 4),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn read_mates() {
+        let r1 = "@r1/1\nACT\n+\nIII\n@r2/1\nGGG\n+\nIII\n";
+        let r2 = "@r1/2\nTTT\n+\nIII\n@r2/2\nCCC\n+\nIII\n";
+        let mates: Vec<_> = MateReader::new(vec![r1.as_bytes(), r2.as_bytes()])
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(mates.len(), 2);
+        assert_eq!(mates[1][1].seq, b"CCC");
+
+        // one mate truncated
+        let r2 = "@r1/2\nTTT\n+\nIII\n";
+        let mut reader = MateReader::new(vec![r1.as_bytes(), r2.as_bytes()]);
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next().unwrap() {
+            Err(Error::Desynchronized { record, .. }) => assert_eq!(record, 2),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // mate names disagree
+        let r2 = "@r2/2\nTTT\n+\nIII\n";
+        match MateReader::new(vec![r1.as_bytes(), r2.as_bytes()])
+            .next()
+            .unwrap()
+        {
+            Err(Error::Desynchronized { record, message }) => {
+                assert_eq!(record, 1);
+                assert!(message.contains("'r1/1' and 'r2/2'"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn assign_multi() {
+        let i1 = "@r1 1:N\nACT\n+\nIII\n@r2 1:N\nAGT\n+\nIII\n";
+        let r1 = "@r1 2:N\nGGGAAC\n+\nIIIIII\n@r2 2:N\nGG\n+\nII\n";
+        let structure: MultiReadStructure = "3B 3C+T".parse().unwrap();
+        let samples = Disambiseq::from_slice(&["ACT".to_string()]);
+        let cells = Disambiseq::from_slice(&["GGG".to_string()]);
+        let reads: Vec<_> = assign_mates(
+            vec![i1.as_bytes(), r1.as_bytes()],
+            &structure,
+            &[&samples, &cells],
+        )
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+        assert_eq!(reads[0].name, "r1 1:N");
+        assert!(reads[0].read.as_ref().unwrap().is_assigned());
+        assert!(reads[1].read.is_none());
+
+        // mismatched readers or indices fail before reading
+        assert!(matches!(
+            assign_mates(vec![i1.as_bytes()], &structure, &[&samples, &cells]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+        assert!(matches!(
+            assign_mates(vec![i1.as_bytes(), r1.as_bytes()], &structure, &[&samples]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+    }
+
+    #[test]
+    fn extract_bounds() {
+        assert_eq!(extract(b"NNACT", 2, 3), Some("ACT"));
+        assert_eq!(extract(b"NNACT", 3, 3), None);
+        assert_eq!(extract(b"NNACT", usize::MAX, 3), None);
+    }
+
+    #[test]
+    fn assign() {
+        let dsq = Disambiseq::from_slice(&["ACT".to_string(), "AGT".to_string()]);
+        let reads: Vec<_> = assign_fastq(FASTQ.as_bytes(), &dsq, 2, 3)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(reads[0].barcode, "ACT");
+        assert_eq!(reads[0].assignment.parent().unwrap().sequence(), "ACT");
+        assert_eq!(reads[1].barcode, "TCT");
+        assert_eq!(reads[1].assignment.parent().unwrap().sequence(), "ACT");
+        assert_eq!(reads[2].assignment, Assignment::Ambiguous);
+        assert_eq!(reads[3].assignment, Assignment::Unassigned);
+
+        let mut summary = CorrectionSummary::default();
+        reads.iter().for_each(|r| summary.add(&r.assignment));
+        assert_eq!(summary.exact, 1);
+        assert_eq!(summary.corrected, 1);
+        assert_eq!(summary.ambiguous, 1);
+        assert_eq!(summary.unassigned, 1);
+    }
+}
@@ -0,0 +1,351 @@
+use crate::{
+    error::{Error, Result},
+    fastq::FastqReader,
+    search::ReadMatch,
+    two_pass::{Assignment, CorrectionSummary},
+    utils::validate_sequence,
+    Disambiseq,
+};
+use std::io::{BufRead, Write};
+#[cfg(feature = "io")]
+use std::path::Path;
+
+/// A single guide of a CRISPR screen library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guide {
+    pub id: String,
+    pub gene: String,
+    pub sequence: String,
+}
+
+/// Counts guides of a CRISPR screen library across FASTQ samples.
+///
+/// Guides are searched at a fixed offset or at every offset of a range,
+/// corrected against their one-mismatch aliases and counted per sample.
+/// Counts are written as a MAGeCK count table.
+#[derive(Debug, Clone, Default)]
+pub struct GuideCounter {
+    /// The library with the index of each guide as payload
+    index: Disambiseq<usize>,
+    guides: Vec<Guide>,
+    /// Guides dropped because they repeat the sequence of an earlier guide
+    duplicates: Vec<Guide>,
+    min_offset: usize,
+    max_offset: usize,
+    samples: Vec<String>,
+    /// The counts of each guide for each sample
+    counts: Vec<Vec<u64>>,
+    summaries: Vec<CorrectionSummary>,
+}
+impl GuideCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a counter from `(id, gene, sequence)` rows
+    pub fn from_guides<S: AsRef<str>>(guides: &[(S, S, S)]) -> Self {
+        let mut counter = Self::new();
+        guides.iter().for_each(|(id, gene, seq)| {
+            counter.add_guide(id.as_ref(), gene.as_ref(), seq.as_ref())
+        });
+        counter
+    }
+
+    /// Reads a MAGeCK library file with the columns `id`, `sequence` and
+    /// `gene` separated by commas or tabs. A header line is skipped if
+    /// its sequence column is not a nucleotide sequence.
+    pub fn from_library<R: BufRead>(reader: R) -> Result<Self> {
+        let mut counter = Self::new();
+        let mut first = true;
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let header = std::mem::replace(&mut first, false);
+            let delimiter = if line.contains('\t') { '\t' } else { ',' };
+            let fields: Vec<&str> = line.split(delimiter).map(|f| f.trim()).collect();
+            if fields.len() < 3 {
+                return Err(Error::malformed(
+                    idx + 1,
+                    format!("expected 3 fields but found {}", fields.len()),
+                ));
+            }
+            let sequence = fields[1].to_ascii_uppercase();
+            if let Err(err) = validate_sequence(&sequence, idx + 1) {
+                if header {
+                    continue;
+                }
+                return Err(err);
+            }
+            counter.add_guide(fields[0], fields[2], &sequence);
+        }
+        Ok(counter)
+    }
+
+    /// Adds a guide to the library. A guide repeating the sequence of a
+    /// previous guide is not counted and is recorded in
+    /// [`duplicates`](Self::duplicates) instead.
+    pub fn add_guide(&mut self, id: &str, gene: &str, sequence: &str) {
+        let guide = Guide {
+            id: id.to_string(),
+            gene: gene.to_string(),
+            sequence: sequence.to_string(),
+        };
+        let duplicate = self.index.parents().contains(sequence);
+        // duplicates are tallied by the index and keep the first payload
+        self.index.insert_named(sequence, self.guides.len());
+        if duplicate {
+            self.duplicates.push(guide);
+            return;
+        }
+        self.guides.push(guide);
+        self.counts.iter_mut().for_each(|c| c.push(0));
+    }
+
+    /// Searches for guides at a fixed offset of each read
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.min_offset = offset;
+        self.max_offset = offset;
+        self
+    }
+
+    /// Searches for guides at every offset between `min` and `max`
+    /// (inclusive) of each read, e.g. for staggered primers
+    pub fn with_offset_range(mut self, min: usize, max: usize) -> Self {
+        self.min_offset = min.min(max);
+        self.max_offset = min.max(max);
+        self
+    }
+
+    pub fn guides(&self) -> &[Guide] {
+        &self.guides
+    }
+
+    /// Returns the guides that were dropped because their sequence
+    /// repeats an earlier guide, in library order
+    pub fn duplicates(&self) -> &[Guide] {
+        &self.duplicates
+    }
+
+    pub fn index(&self) -> &Disambiseq<usize> {
+        &self.index
+    }
+
+    /// Returns the names of the samples counted so far
+    pub fn samples(&self) -> &[String] {
+        &self.samples
+    }
+
+    /// Returns the count of a guide id in a sample
+    pub fn count(&self, sample: &str, id: &str) -> Option<u64> {
+        let s = self.samples.iter().position(|s| s == sample)?;
+        let g = self.guides.iter().position(|g| g.id == id)?;
+        Some(self.counts[s][g])
+    }
+
+    /// Returns the assignment summary of a sample
+    pub fn summary(&self, sample: &str) -> Option<&CorrectionSummary> {
+        let s = self.samples.iter().position(|s| s == sample)?;
+        Some(&self.summaries[s])
+    }
+
+    /// Searches a read for a guide with
+    /// [`find_in_read`](Disambiseq::find_in_read) over the offset range.
+    ///
+    /// Exact matches are preferred over corrected ones. A read is
+    /// ambiguous if different offsets match different guides equally
+    /// well, or if no offset yields a guide but some offset is ambiguous.
+    pub fn assign(&self, seq: &[u8]) -> Assignment {
+        match self
+            .index
+            .find_in_read(seq, self.min_offset..=self.max_offset)
+        {
+            ReadMatch::Found {
+                parent,
+                exact: true,
+                ..
+            } => Assignment::Exact(parent),
+            ReadMatch::Found { parent, .. } => Assignment::Corrected(parent),
+            ReadMatch::Conflict(_) | ReadMatch::Ambiguous => Assignment::Ambiguous,
+            ReadMatch::NotFound => Assignment::Unassigned,
+        }
+    }
+
+    /// Returns the position of a sample adding it if it is new
+    fn sample_index(&mut self, sample: &str) -> usize {
+        match self.samples.iter().position(|s| s == sample) {
+            Some(s) => s,
+            None => {
+                self.samples.push(sample.to_string());
+                self.counts.push(vec![0; self.guides.len()]);
+                self.summaries.push(CorrectionSummary::default());
+                self.samples.len() - 1
+            }
+        }
+    }
+
+    /// Counts the guides of every record of a FASTQ stream into a sample.
+    /// Counting the same sample again accumulates its counts.
+    pub fn count_sample<R: BufRead>(&mut self, sample: &str, reader: R) -> Result<()> {
+        let s = self.sample_index(sample);
+        for record in FastqReader::new(reader) {
+            let assignment = self.assign(&record?.seq);
+            if let Some(parent) = assignment.parent() {
+                if let Some(&g) = self.index.payload(parent.sequence()) {
+                    self.counts[s][g] += 1;
+                }
+            }
+            self.summaries[s].add(&assignment);
+        }
+        Ok(())
+    }
+
+    /// Opens a (possibly gzipped) FASTQ file and counts its guides into a
+    /// sample
+    #[cfg(feature = "io")]
+    pub fn count_path<P: AsRef<Path>>(&mut self, sample: &str, path: P) -> Result<()> {
+        self.count_sample(sample, crate::io::open(path)?)
+    }
+
+    /// Writes the MAGeCK count table with the columns `sgRNA`, `Gene` and
+    /// one column per sample, in library order
+    pub fn write_count_table<W: Write>(&self, mut writer: W) -> Result<()> {
+        write!(writer, "sgRNA\tGene")?;
+        for sample in &self.samples {
+            write!(writer, "\t{sample}")?;
+        }
+        writeln!(writer)?;
+        for (g, guide) in self.guides.iter().enumerate() {
+            write!(writer, "{}\t{}", guide.id, guide.gene)?;
+            for counts in &self.counts {
+                write!(writer, "\t{}", counts[g])?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    const LIBRARY: &str = "sgRNA,Sequence,Gene\n\
+                           g1,ACTGACTG,GENE1\n\
+                           g2,GGGGCCCC,GENE1\n\
+                           g3,TTTTAAAA,GENE2\n";
+
+    fn fastq(seqs: &[&str]) -> String {
+        seqs.iter()
+            .enumerate()
+            .map(|(i, s)| format!("@r{i}\n{s}\n+\n{}\n", "I".repeat(s.len())))
+            .collect()
+    }
+
+    #[test]
+    fn read_library() {
+        let counter = GuideCounter::from_library(LIBRARY.as_bytes()).unwrap();
+        assert_eq!(counter.guides().len(), 3);
+        assert_eq!(counter.guides()[1].gene, "GENE1");
+        assert_eq!(counter.guides()[2].sequence, "TTTTAAAA");
+
+        let tsv = "g1\tACTGACTG\tGENE1\ng1b\tACTGACTG\tGENE2\n";
+        let counter = GuideCounter::from_library(tsv.as_bytes()).unwrap();
+        assert_eq!(counter.guides().len(), 1);
+        assert_eq!(counter.duplicates().len(), 1);
+        assert_eq!(counter.duplicates()[0].id, "g1b");
+        assert_eq!(counter.duplicates()[0].gene, "GENE2");
+        assert_eq!(counter.index().duplicates()["ACTGACTG"], 1);
+
+        // the header is the first non-empty line
+        let headed = "\nsgRNA,sequence,gene\ng1,ACTGACTG,GENE1\n";
+        let counter = GuideCounter::from_library(headed.as_bytes()).unwrap();
+        assert_eq!(counter.guides().len(), 1);
+        assert_eq!(counter.guides()[0].id, "g1");
+
+        let invalid = "g1,ACTGACTG,GENE1\ng2,ACTGXCTG,GENE2\n";
+        match GuideCounter::from_library(invalid.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn fixed_offset() {
+        let mut counter = GuideCounter::from_library(LIBRARY.as_bytes())
+            .unwrap()
+            .with_offset(2);
+        let reads = fastq(&["NNACTGACTGNN", "NNACTGACTANN", "NNGGGGCCCCNN", "ACTGACTG"]);
+        counter.count_sample("s1", reads.as_bytes()).unwrap();
+        assert_eq!(counter.count("s1", "g1"), Some(2));
+        assert_eq!(counter.count("s1", "g2"), Some(1));
+        assert_eq!(counter.count("s1", "g3"), Some(0));
+
+        let summary = counter.summary("s1").unwrap();
+        assert_eq!(summary.exact, 2);
+        assert_eq!(summary.corrected, 1);
+        assert_eq!(summary.unassigned, 1);
+    }
+
+    #[test]
+    fn variable_offset() {
+        let counter = GuideCounter::from_library(LIBRARY.as_bytes())
+            .unwrap()
+            .with_offset_range(0, 3);
+        assert_eq!(
+            counter.assign(b"NNNTTTTAAAA").parent().unwrap().sequence(),
+            "TTTTAAAA"
+        );
+        // an exact match at a later offset beats a correction at an earlier one
+        assert!(matches!(
+            counter.assign(b"AGGGGCCCCN"),
+            Assignment::Exact(_)
+        ));
+        assert_eq!(counter.assign(b"NNNNNNNNNN"), Assignment::Unassigned);
+    }
+
+    #[test]
+    fn conflicting_offsets() {
+        let counter =
+            GuideCounter::from_guides(&[("g1", "GENE1", "AAAA"), ("g2", "GENE2", "CCCC")])
+                .with_offset_range(0, 3);
+        // AAAC corrects to g1 and ACCC and CCCT correct to g2
+        assert_eq!(counter.assign(b"AAACCCT"), Assignment::Ambiguous);
+        // corrections to the same guide at several offsets agree
+        assert_eq!(
+            counter.assign(b"ACCCT").parent().unwrap().sequence(),
+            "CCCC"
+        );
+        // exact matches of different guides agree with the barcode search
+        let counter = counter.with_offset_range(0, 4);
+        assert_eq!(counter.assign(b"AAAACCCC"), Assignment::Ambiguous);
+        assert!(matches!(
+            counter.index().find_in_read("AAAACCCC", 0..=4),
+            ReadMatch::Conflict(_)
+        ));
+    }
+
+    #[test]
+    fn count_table() {
+        let mut counter =
+            GuideCounter::from_guides(&[("g1", "GENE1", "ACTG"), ("g2", "GENE2", "GGCC")]);
+        counter
+            .count_sample("plasmid", fastq(&["ACTG", "ACTG", "GGCC"]).as_bytes())
+            .unwrap();
+        counter
+            .count_sample("day14", fastq(&["GGCC"]).as_bytes())
+            .unwrap();
+        counter
+            .count_sample("day14", fastq(&["GGCA"]).as_bytes())
+            .unwrap();
+
+        let mut out = Vec::new();
+        counter.write_count_table(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "sgRNA\tGene\tplasmid\tday14\ng1\tGENE1\t2\t0\ng2\tGENE2\t1\t2\n"
+        );
+    }
+}
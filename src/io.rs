@@ -0,0 +1,492 @@
+use crate::{
+    error::{Error, Result},
+    strand::Strand,
+    utils::{hamming_distance, reverse_complement_bytes, validate_sequence},
+    Disambiseq,
+};
+use flate2::read::MultiGzDecoder;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+/// The magic bytes starting a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Wraps a reader in a buffered reader, transparently decompressing it
+/// if it is a gzip stream.
+pub fn decode<R: Read + 'static>(reader: R) -> Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Opens a (possibly gzipped) file for buffered reading
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<dyn BufRead>> {
+    decode(File::open(path)?)
+}
+
+/// Reads a whitelist of one sequence per line (e.g. 10x
+/// `3M-february-2018.txt`). Empty lines are skipped.
+pub fn read_whitelist<R: BufRead>(reader: R) -> Result<Disambiseq> {
+    let mut dsq = Disambiseq::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let seq = line.trim();
+        if seq.is_empty() {
+            continue;
+        }
+        validate_sequence(seq, idx + 1)?;
+        dsq.insert(seq);
+    }
+    Ok(dsq)
+}
+
+/// Loads a (possibly gzipped) whitelist file of one sequence per line
+pub fn load_whitelist<P: AsRef<Path>>(path: P) -> Result<Disambiseq> {
+    read_whitelist(open(path)?)
+}
+
+/// A column of a delimited table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// The 0-based index of the column
+    Index(usize),
+    /// The name of the column in the header
+    Name(String),
+}
+impl Column {
+    fn resolve(&self, header: Option<&[&str]>) -> Result<usize> {
+        match (self, header) {
+            (Self::Index(idx), _) => Ok(*idx),
+            (Self::Name(name), Some(header)) => header
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| Error::malformed(1, format!("missing column '{name}' in header"))),
+            (Self::Name(name), None) => Err(Error::malformed(
+                1,
+                format!("cannot select column '{name}' in a table without a header"),
+            )),
+        }
+    }
+}
+
+/// Layout of a delimited guide or barcode table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableOptions {
+    pub delimiter: char,
+    pub header: bool,
+    pub id: Column,
+    pub sequence: Column,
+}
+impl TableOptions {
+    /// A comma-separated table with a header
+    pub fn csv(id: Column, sequence: Column) -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            id,
+            sequence,
+        }
+    }
+
+    /// A tab-separated table with a header
+    pub fn tsv(id: Column, sequence: Column) -> Self {
+        Self {
+            delimiter: '\t',
+            header: true,
+            id,
+            sequence,
+        }
+    }
+
+    /// Sets whether the first line of the table is a header
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+/// Reads a delimited table of sequences using the id column as each
+/// parent's payload. Empty lines are skipped.
+pub fn read_table<R: BufRead>(reader: R, options: &TableOptions) -> Result<Disambiseq<String>> {
+    let mut dsq = Disambiseq::named();
+    let mut columns = None;
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(options.delimiter).map(|f| f.trim()).collect();
+
+        let (id_col, seq_col) = match columns {
+            Some(cols) => cols,
+            None => {
+                let header = options.header.then_some(fields.as_slice());
+                let cols = (
+                    options.id.resolve(header)?,
+                    options.sequence.resolve(header)?,
+                );
+                columns = Some(cols);
+                if options.header {
+                    continue;
+                }
+                cols
+            }
+        };
+
+        let (Some(id), Some(seq)) = (fields.get(id_col), fields.get(seq_col)) else {
+            return Err(Error::malformed(
+                idx + 1,
+                format!("expected at least {} fields", id_col.max(seq_col) + 1),
+            ));
+        };
+        validate_sequence(seq, idx + 1)?;
+        dsq.insert_named(seq, id.to_string());
+    }
+    Ok(dsq)
+}
+
+/// Loads a (possibly gzipped) delimited table of sequences
+pub fn load_table<P: AsRef<Path>>(path: P, options: &TableOptions) -> Result<Disambiseq<String>> {
+    read_table(open(path)?, options)
+}
+
+/// A feature of a 10x `feature_reference.csv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub id: String,
+    pub name: String,
+    pub read: String,
+    pub pattern: String,
+    pub feature_type: String,
+}
+
+/// Reads a 10x `feature_reference.csv` keyed by the feature sequences
+pub fn read_feature_reference<R: BufRead>(reader: R) -> Result<Disambiseq<Feature>> {
+    let mut dsq = Disambiseq::named();
+    let mut lines = reader.lines().enumerate();
+
+    let header = loop {
+        match lines.next() {
+            Some((_, line)) => {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    break line;
+                }
+            }
+            None => return Ok(dsq),
+        }
+    };
+    let header: Vec<&str> = header
+        .trim_end_matches('\r')
+        .split(',')
+        .map(|f| f.trim())
+        .collect();
+    let column = |name: &str| Column::Name(name.to_string()).resolve(Some(&header));
+    let id_col = column("id")?;
+    let name_col = column("name")?;
+    let read_col = column("read")?;
+    let pattern_col = column("pattern")?;
+    let seq_col = column("sequence")?;
+    let type_col = column("feature_type")?;
+    let n_fields = header.len();
+
+    for (idx, line) in lines {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() < n_fields {
+            return Err(Error::malformed(
+                idx + 1,
+                format!("expected {} fields but found {}", n_fields, fields.len()),
+            ));
+        }
+        validate_sequence(fields[seq_col], idx + 1)?;
+        dsq.insert_named(
+            fields[seq_col],
+            Feature {
+                id: fields[id_col].to_string(),
+                name: fields[name_col].to_string(),
+                read: fields[read_col].to_string(),
+                pattern: fields[pattern_col].to_string(),
+                feature_type: fields[type_col].to_string(),
+            },
+        );
+    }
+    Ok(dsq)
+}
+
+/// Loads a 10x `feature_reference.csv`
+pub fn load_feature_reference<P: AsRef<Path>>(path: P) -> Result<Disambiseq<Feature>> {
+    read_feature_reference(open(path)?)
+}
+
+impl Disambiseq<String> {
+    /// Builds an index from a FASTA library using the record names (up to
+    /// the first whitespace) as parent ids.
+    pub fn from_fasta<R: BufRead>(reader: R) -> Result<Self> {
+        let mut dsq = Self::named();
+        let mut record: Option<(String, String, usize)> = None;
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('>') {
+                if let Some(record) = record.take() {
+                    dsq.insert_fasta_record(record)?;
+                }
+                let name = header.split_whitespace().next().unwrap_or_default();
+                if name.is_empty() {
+                    return Err(Error::malformed(idx + 1, "missing record name"));
+                }
+                record = Some((name.to_string(), String::new(), idx + 1));
+            } else if let Some((_, seq, _)) = record.as_mut() {
+                seq.push_str(line);
+            } else {
+                return Err(Error::malformed(idx + 1, "sequence found before header"));
+            }
+        }
+        if let Some(record) = record {
+            dsq.insert_fasta_record(record)?;
+        }
+        Ok(dsq)
+    }
+
+    fn insert_fasta_record(&mut self, (name, seq, line): (String, String, usize)) -> Result<()> {
+        validate_sequence(&seq, line)?;
+        self.insert_named(&seq, name);
+        Ok(())
+    }
+}
+
+impl<T> Disambiseq<T> {
+    /// Writes the full correction table as TSV with the columns `alias`,
+    /// `parent`, `distance` and `strand` (`+` or `-`), sorted by alias.
+    ///
+    /// Parents are included as their own aliases at distance zero unless
+    /// they were only indexed on the reverse strand. In canonical mode the
+    /// aliases are written in their canonical form.
+    pub fn write_table<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut rows: Vec<_> = self
+            .parents()
+            .iter()
+            .filter_map(|p| {
+                self.get_parent_with_strand(p.sequence())
+                    .map(|(parent, strand)| (p, parent, strand))
+            })
+            .chain(
+                self.unambiguous()
+                    .iter()
+                    .map(|(alias, a)| (alias, &a.parent, a.strand)),
+            )
+            .collect();
+        rows.sort_unstable_by(|a, b| a.0.sequence().cmp(b.0.sequence()));
+
+        writeln!(writer, "alias\tparent\tdistance\tstrand")?;
+        for (alias, parent, strand) in rows {
+            let distance = match strand {
+                Strand::Forward => {
+                    hamming_distance(alias.sequence().as_bytes(), parent.sequence().as_bytes())
+                }
+                Strand::Reverse => hamming_distance(
+                    alias.sequence().as_bytes(),
+                    &reverse_complement_bytes(parent.sequence().as_bytes()),
+                ),
+            }
+            .expect("aliases have the length of their parent");
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                alias.sequence(),
+                parent.sequence(),
+                distance,
+                strand
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the parent set as FASTA using each sequence as its record
+    /// name, sorted by sequence
+    pub fn write_fasta<W: Write>(&self, mut writer: W) -> Result<()> {
+        for parent in self.sorted_parents() {
+            writeln!(writer, ">{}\n{}", parent, parent)?;
+        }
+        Ok(())
+    }
+
+    fn sorted_parents(&self) -> Vec<&str> {
+        let mut parents: Vec<&str> = self.parents().iter().map(|p| p.sequence()).collect();
+        parents.sort_unstable();
+        parents
+    }
+}
+
+impl<T: Display> Disambiseq<T> {
+    /// Writes the parent set as FASTA using each payload as its record
+    /// name (falling back to the sequence), sorted by sequence
+    pub fn write_named_fasta<W: Write>(&self, mut writer: W) -> Result<()> {
+        for parent in self.sorted_parents() {
+            match self.payload(parent) {
+                Some(name) => writeln!(writer, ">{}\n{}", name, parent)?,
+                None => writeln!(writer, ">{}\n{}", parent, parent)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn whitelist() {
+        let data = "ACT\nAGT\n\n";
+        let dsq = read_whitelist(data.as_bytes()).unwrap();
+        assert_eq!(dsq.parents().len(), 2);
+        assert_eq!(dsq.get_parent("TCT").unwrap().sequence(), "ACT");
+    }
+
+    #[test]
+    fn whitelist_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"ACT\nAGT\n").unwrap();
+        let bytes = encoder.finish().unwrap();
+        let reader = decode(Cursor::new(bytes)).unwrap();
+        let dsq = read_whitelist(reader).unwrap();
+        assert_eq!(dsq.parents().len(), 2);
+    }
+
+    #[test]
+    fn whitelist_malformed() {
+        let data = "ACT\nAGT\nAXT\n";
+        match read_whitelist(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 3),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn table_named_columns() {
+        let data = "gene\tsgRNA\tsequence\nTP53\tg1\tACT\nKRAS\tg2\tAGT\n";
+        let options = TableOptions::tsv(
+            Column::Name("sgRNA".to_string()),
+            Column::Name("sequence".to_string()),
+        );
+        let dsq = read_table(data.as_bytes(), &options).unwrap();
+        assert_eq!(dsq.get_named("TCT").unwrap(), "g1");
+        assert_eq!(dsq.get_named("AGG").unwrap(), "g2");
+    }
+
+    #[test]
+    fn table_indexed_columns() {
+        let data = "g1,ACT\ng2,AGT\n";
+        let options = TableOptions::csv(Column::Index(0), Column::Index(1)).with_header(false);
+        let dsq = read_table(data.as_bytes(), &options).unwrap();
+        assert_eq!(dsq.get_named("ACT").unwrap(), "g1");
+    }
+
+    #[test]
+    fn table_malformed() {
+        let data = "id,sequence\ng1,ACT\ng2\n";
+        let options = TableOptions::csv(Column::Index(0), Column::Index(1));
+        match read_table(data.as_bytes(), &options) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 3),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let options = TableOptions::csv(Column::Name("guide".to_string()), Column::Index(1));
+        assert!(read_table(data.as_bytes(), &options).is_err());
+    }
+
+    #[test]
+    fn feature_reference() {
+        let data = "id,name,read,pattern,sequence,feature_type\n\
+                    g1,TP53-1,R2,5PNNNNNNNNNN(BC),ACT,CRISPR Guide Capture\n\
+                    g2,KRAS-1,R2,5PNNNNNNNNNN(BC),AGT,CRISPR Guide Capture\n";
+        let dsq = read_feature_reference(data.as_bytes()).unwrap();
+        let feature = dsq.get_named("TCT").unwrap();
+        assert_eq!(feature.id, "g1");
+        assert_eq!(feature.name, "TP53-1");
+        assert_eq!(feature.read, "R2");
+        assert_eq!(feature.feature_type, "CRISPR Guide Capture");
+    }
+
+    #[test]
+    fn feature_reference_malformed() {
+        let data = "id,name,read,pattern,sequence,feature_type\n\
+                    g1,TP53-1,R2,5PNNNNNNNNNN(BC),ACT\n";
+        match read_feature_reference(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn fasta() {
+        let data = ">g1 TP53\nAC\nT\n>g2\nAGT\n";
+        let dsq = Disambiseq::from_fasta(data.as_bytes()).unwrap();
+        assert_eq!(dsq.parents().len(), 2);
+        assert_eq!(dsq.get_named("TCT").unwrap(), "g1");
+        assert_eq!(dsq.get_named("AGG").unwrap(), "g2");
+    }
+
+    #[test]
+    fn fasta_malformed() {
+        let data = "ACT\n>g1\nACT\n";
+        match Disambiseq::from_fasta(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 1),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        // the empty record is reported at its header
+        let data = ">g1\nACT\n>g2\n>g3\nAGT\n";
+        match Disambiseq::from_fasta(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 3),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn fasta_roundtrip() {
+        let data = ">g2\nAGT\n>g1\nACT\n";
+        let dsq = Disambiseq::from_fasta(data.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        dsq.write_named_fasta(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ">g1\nACT\n>g2\nAGT\n");
+
+        let mut out = Vec::new();
+        dsq.write_fasta(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ">ACT\nACT\n>AGT\nAGT\n");
+    }
+
+    #[test]
+    fn correction_table() {
+        let mut dsq = Disambiseq::new();
+        dsq.insert_with_reverse_complement("ACTAA");
+        let mut out = Vec::new();
+        dsq.write_table(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "alias\tparent\tdistance\tstrand");
+        assert_eq!(lines.len(), 1 + 1 + 1 + 15 + 15);
+        assert!(lines.contains(&"ACTAA\tACTAA\t0\t+"));
+        assert!(lines.contains(&"ACTAT\tACTAA\t1\t+"));
+        assert!(lines.contains(&"TTAGT\tACTAA\t0\t-"));
+        assert!(lines.contains(&"TTAGA\tACTAA\t1\t-"));
+    }
+}
@@ -74,13 +74,91 @@
 //! assert_eq!(dsq.get_parent("AAT"), None);
 //! assert_eq!(dsq.get_parent("ATT"), None);
 //! ```
+//!
+//! ### Resolving ambiguous sequences with parent abundances
+//!
+//! ```rust
+//! use disambiseq::{AbundancePrior, Disambiseq};
+//!
+//! let sequences = vec![
+//!     "ACT".to_string(),
+//!     "AGT".to_string()
+//! ];
+//! let dsq = Disambiseq::from_slice(&sequences);
+//!
+//! // counts of exact parent matches from a first pass
+//! let mut prior = AbundancePrior::new();
+//! prior.add("ACT", 1000);
+//! prior.add("AGT", 3);
+//!
+//! // ambiguous sequences resolve to the overwhelmingly likely parent
+//! assert_eq!(dsq.get_parent_with_prior("ATT", None, &prior).unwrap().sequence(), "ACT");
+//! ```
+//!
+//! ## Features
+//!
+//! - `io` (off by default): the `io` module for loading whitelists,
+//!   barcode tables, feature references and FASTA files and for writing
+//!   correction tables, plus path-based helpers such as
+//!   `assign_fastq_path`. Files may be gzipped (via `flate2`).
+//!
+//! FASTQ parsing, demultiplexing and guide counting work on any reader or
+//! writer and are always available.
 
+mod anchor;
+mod collision;
+mod combinatorial;
+mod demux;
+mod design;
 mod disambibyte;
 mod disambiseq;
+mod distance;
+mod dual_index;
+mod error;
+mod fastq;
+mod guide;
+#[cfg(feature = "io")]
+pub mod io;
+mod prior;
+mod read_structure;
+mod report;
+mod sample_sheet;
+mod search;
 mod sequence;
+mod strand;
+mod two_pass;
+mod umi;
 mod utils;
+mod whitelist;
 pub use crate::{
+    anchor::{AnchorExtractor, AnchorMatch, AnchorOutcome},
+    collision::{CollisionEdge, CollisionGraph, Pruning},
+    combinatorial::{CombinatorialDecoder, Decoded},
+    demux::{Demultiplexer, DemuxSummary, AMBIGUOUS, DEFAULT_MAX_OPEN_FILES, UNASSIGNED},
+    design::BarcodeDesigner,
     disambibyte::{ByteWrapper, Disambibyte},
-    disambiseq::{Disambiseq, SeqWrapper},
+    disambiseq::{Alias, Disambiseq, SeqWrapper},
+    distance::{DistanceMatrix, DistanceMetric, PairwiseDistances},
+    dual_index::{header_indices, DualAssignment, DualIndex, DualIndexSummary},
+    error::{Error, Result},
+    fastq::{
+        assign_fastq, assign_mates, AssignedMates, AssignedRead, FastqReader, FastqRecord,
+        MateReader,
+    },
+    guide::{Guide, GuideCounter},
+    prior::AbundancePrior,
+    read_structure::{
+        ExtractedRead, MultiReadStructure, ReadStructure, Segment, SegmentKind, StructuredRead,
+    },
+    report::{LibraryIssue, LibraryReport, ParentReport},
+    sample_sheet::{IndexCollision, SampleSheet, SampleSheetRow},
+    search::ReadMatch,
     sequence::Sequence,
+    strand::{strand_collisions, Strand, StrandCollision, StrandPolicy},
+    two_pass::{Assignment, CorrectionSummary, Corrections, TwoPassCorrector},
+    umi::{CollapsedUmis, UmiCollapser, UmiMethod},
+    whitelist::{CurvePoint, InferredWhitelist, WhitelistBuilder},
 };
+
+#[cfg(feature = "io")]
+pub use crate::fastq::assign_fastq_path;
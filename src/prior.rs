@@ -0,0 +1,219 @@
+use crate::{
+    disambiseq::SeqWrapper,
+    utils::{mismatch_position, reverse_complement},
+};
+use hashbrown::HashMap;
+
+/// Default pseudocount added to every parent abundance
+pub const DEFAULT_PSEUDOCOUNT: f64 = 1.0;
+
+/// Default likelihood ratio required between the best and second best
+/// candidate (equivalent to a 0.975 posterior between two candidates).
+pub const DEFAULT_THRESHOLD: f64 = 39.0;
+
+/// Error probability assumed at the mismatch position when no base
+/// qualities are provided.
+const DEFAULT_ERROR_PROBABILITY: f64 = 0.01;
+
+/// Observed parent abundances used to resolve ambiguous sequences to
+/// their most likely parent.
+#[derive(Debug, Clone)]
+pub struct AbundancePrior {
+    counts: HashMap<String, u64>,
+    pseudocount: f64,
+    threshold: f64,
+}
+impl Default for AbundancePrior {
+    fn default() -> Self {
+        Self {
+            counts: HashMap::new(),
+            pseudocount: DEFAULT_PSEUDOCOUNT,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+impl AbundancePrior {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pseudocount added to every parent abundance
+    pub fn with_pseudocount(mut self, pseudocount: f64) -> Self {
+        self.pseudocount = pseudocount;
+        self
+    }
+
+    /// Sets the likelihood ratio the best candidate must reach over the
+    /// second best candidate to be assigned
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Adds `count` observations of a parent sequence
+    pub fn add(&mut self, parent: &str, count: u64) {
+        if let Some(c) = self.counts.get_mut(parent) {
+            *c += count;
+        } else {
+            self.counts.insert(parent.to_string(), count);
+        }
+    }
+
+    /// Adds a single observation of a parent sequence
+    pub fn increment(&mut self, parent: &str) {
+        self.add(parent, 1);
+    }
+
+    /// Returns the number of observations of a parent sequence
+    pub fn count(&self, parent: &str) -> u64 {
+        self.counts.get(parent).copied().unwrap_or(0)
+    }
+
+    /// Returns the total number of observations
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Calculates the likelihood that `seq` was derived from `parent`
+    fn likelihood(&self, seq: &str, qual: Option<&[u8]>, parent: &SeqWrapper) -> f64 {
+        let abundance = self.count(parent.sequence()) as f64 + self.pseudocount;
+        let position =
+            mismatch_position(seq.as_bytes(), parent.sequence().as_bytes()).or_else(|| {
+                // candidates from the reverse strand differ from the
+                // reverse complement of their parent
+                parent
+                    .sequence()
+                    .bytes()
+                    .all(|b| b"ACGTN".contains(&b))
+                    .then(|| reverse_complement(parent.sequence()))
+                    .and_then(|revc| mismatch_position(seq.as_bytes(), revc.as_bytes()))
+            });
+        let error = match (position, qual) {
+            (Some(pos), Some(qual)) if pos < qual.len() => phred_to_probability(qual[pos]),
+            _ => DEFAULT_ERROR_PROBABILITY,
+        };
+        abundance * error
+    }
+
+    /// Resolves a sequence to the most likely of its candidate parents or
+    /// `None` if the likelihood ratio does not reach the threshold or no
+    /// candidate has a positive likelihood.
+    pub fn resolve<'a>(
+        &self,
+        seq: &str,
+        qual: Option<&[u8]>,
+        candidates: &'a [SeqWrapper],
+    ) -> Option<&'a SeqWrapper> {
+        let mut best: Option<(&SeqWrapper, f64)> = None;
+        let mut second = 0.0;
+        for candidate in candidates {
+            let likelihood = self.likelihood(seq, qual, candidate);
+            match best {
+                Some((_, b)) if likelihood <= b => {
+                    if likelihood > second {
+                        second = likelihood;
+                    }
+                }
+                Some((_, b)) => {
+                    second = b;
+                    best = Some((candidate, likelihood));
+                }
+                None => best = Some((candidate, likelihood)),
+            }
+        }
+        let (parent, likelihood) = best?;
+        // unseen candidates carry no evidence without a pseudocount
+        if likelihood <= 0.0 {
+            return None;
+        }
+        if second == 0.0 || likelihood / second >= self.threshold {
+            Some(parent)
+        } else {
+            None
+        }
+    }
+}
+
+/// Converts a phred+33 quality score to an error probability
+fn phred_to_probability(qual: u8) -> f64 {
+    10f64.powf(-(qual.saturating_sub(33) as f64) / 10.0)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use std::sync::Arc;
+
+    fn wrap(seq: &str) -> SeqWrapper {
+        SeqWrapper(Arc::new(seq.to_string()))
+    }
+
+    #[test]
+    fn counts() {
+        let mut prior = AbundancePrior::new();
+        prior.add("ACT", 10);
+        prior.increment("ACT");
+        prior.increment("AGT");
+        assert_eq!(prior.count("ACT"), 11);
+        assert_eq!(prior.count("AGT"), 1);
+        assert_eq!(prior.count("TTT"), 0);
+        assert_eq!(prior.total(), 12);
+    }
+
+    #[test]
+    fn resolve_abundant() {
+        let candidates = vec![wrap("ACT"), wrap("AGT")];
+        let mut prior = AbundancePrior::new();
+        prior.add("AGT", 500);
+        let parent = prior.resolve("ATT", None, &candidates).unwrap();
+        assert_eq!(parent.sequence(), "AGT");
+    }
+
+    #[test]
+    fn resolve_threshold() {
+        let candidates = vec![wrap("ACT"), wrap("AGT")];
+        let mut prior = AbundancePrior::new().with_threshold(2.0);
+        prior.add("ACT", 5);
+        prior.add("AGT", 1);
+        assert_eq!(
+            prior.resolve("ATT", None, &candidates).unwrap().sequence(),
+            "ACT"
+        );
+        let prior = prior.with_threshold(10.0);
+        assert_eq!(prior.resolve("ATT", None, &candidates), None);
+    }
+
+    #[test]
+    fn resolve_unseen() {
+        let candidates = vec![wrap("ACT"), wrap("AGT")];
+        let mut prior = AbundancePrior::new().with_pseudocount(0.0);
+        assert_eq!(prior.resolve("ATT", None, &candidates), None);
+
+        // a single observed candidate still resolves
+        prior.increment("AGT");
+        assert_eq!(
+            prior.resolve("ATT", None, &candidates).unwrap().sequence(),
+            "AGT"
+        );
+    }
+
+    #[test]
+    fn resolve_qualities() {
+        // ACT and AGC are both one mismatch from AGT at different positions
+        let candidates = vec![wrap("ACT"), wrap("AGC")];
+        let mut prior = AbundancePrior::new();
+        prior.add("ACT", 100);
+        prior.add("AGC", 100);
+
+        // low quality at position 1 favours ACT
+        let parent = prior.resolve("AGT", Some(b"I#I"), &candidates).unwrap();
+        assert_eq!(parent.sequence(), "ACT");
+
+        // low quality at position 2 favours AGC
+        let parent = prior.resolve("AGT", Some(b"II#"), &candidates).unwrap();
+        assert_eq!(parent.sequence(), "AGC");
+
+        // equal qualities cannot separate the candidates
+        assert_eq!(prior.resolve("AGT", Some(b"III"), &candidates), None);
+    }
+}
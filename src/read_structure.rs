@@ -0,0 +1,501 @@
+use crate::{
+    error::{Error, Result},
+    fastq::FastqRecord,
+    two_pass::Assignment,
+    Disambiseq,
+};
+use std::{borrow::Borrow, fmt, str::FromStr};
+
+/// The kind of bases a read segment contains
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Template bases (`T`)
+    Template,
+    /// Sample barcode bases (`B`)
+    Barcode,
+    /// Cellular barcode bases (`C`)
+    Cellular,
+    /// Molecular identifier (UMI) bases (`M`)
+    Molecular,
+    /// Skipped bases (`S`)
+    Skip,
+}
+impl SegmentKind {
+    fn from_code(code: char) -> Option<Self> {
+        match code {
+            'T' => Some(Self::Template),
+            'B' => Some(Self::Barcode),
+            'C' => Some(Self::Cellular),
+            'M' => Some(Self::Molecular),
+            'S' => Some(Self::Skip),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> char {
+        match self {
+            Self::Template => 'T',
+            Self::Barcode => 'B',
+            Self::Cellular => 'C',
+            Self::Molecular => 'M',
+            Self::Skip => 'S',
+        }
+    }
+
+    /// Returns true if the segment is corrected against an index
+    pub fn is_barcode(&self) -> bool {
+        matches!(self, Self::Barcode | Self::Cellular)
+    }
+}
+
+/// A single segment of a read structure
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    /// The length of the segment or `None` for the remainder of the read
+    pub length: Option<usize>,
+}
+
+/// A read structure in the style of fgbio (e.g. `16B12M+T` or `8B4S8B`)
+/// describing how the bases of a read are laid out.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ReadStructure {
+    segments: Vec<Segment>,
+}
+impl FromStr for ReadStructure {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidReadStructure {
+            structure: s.to_string(),
+            message,
+        };
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut chars = s.chars().peekable();
+        while chars.peek().is_some() {
+            if segments.last().is_some_and(|seg| seg.length.is_none()) {
+                return Err(invalid(
+                    "only the last segment may have a variable length".to_string(),
+                ));
+            }
+
+            let length = if chars.next_if_eq(&'+').is_some() {
+                None
+            } else {
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                match digits.parse::<usize>() {
+                    Ok(0) => return Err(invalid("segment lengths must be positive".to_string())),
+                    Ok(length) => Some(length),
+                    Err(_) => return Err(invalid("expected a segment length or '+'".to_string())),
+                }
+            };
+
+            let kind = match chars.next() {
+                Some(code) => SegmentKind::from_code(code)
+                    .ok_or_else(|| invalid(format!("unknown segment type '{code}'")))?,
+                None => return Err(invalid("missing segment type".to_string())),
+            };
+            segments.push(Segment { kind, length });
+        }
+
+        if segments.is_empty() {
+            return Err(invalid("no segments".to_string()));
+        }
+        Ok(Self { segments })
+    }
+}
+impl fmt::Display for ReadStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment.length {
+                Some(length) => write!(f, "{}{}", length, segment.kind.code())?,
+                None => write!(f, "+{}", segment.kind.code())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The segments extracted from a read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractedRead {
+    /// Barcode (`B` and `C`) segments in order
+    pub barcodes: Vec<String>,
+    /// Molecular identifier segments in order
+    pub umis: Vec<String>,
+    /// Template segment sequences and qualities in order
+    pub templates: Vec<(Vec<u8>, Vec<u8>)>,
+}
+impl ExtractedRead {
+    /// Returns all molecular identifier segments concatenated
+    pub fn umi(&self) -> String {
+        self.umis.concat()
+    }
+}
+
+/// A read whose barcode segments were assigned against their indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuredRead {
+    pub extracted: ExtractedRead,
+    /// The assignment of each barcode segment
+    pub assignments: Vec<Assignment>,
+}
+impl StructuredRead {
+    /// Returns true if every barcode segment was assigned a parent
+    pub fn is_assigned(&self) -> bool {
+        self.assignments.iter().all(|a| a.parent().is_some())
+    }
+}
+
+impl ReadStructure {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the minimum read length required by the structure
+    pub fn fixed_length(&self) -> usize {
+        self.segments.iter().filter_map(|s| s.length).sum()
+    }
+
+    /// Returns the number of barcode (`B` and `C`) segments
+    pub fn num_barcodes(&self) -> usize {
+        self.segments.iter().filter(|s| s.kind.is_barcode()).count()
+    }
+
+    /// Splits a sequence into its segments or returns `None` if it is too
+    /// short. Bases beyond a fixed-length structure are ignored and a
+    /// variable-length last segment may be empty.
+    pub fn split<'r>(&self, seq: &'r [u8]) -> Option<Vec<(SegmentKind, &'r [u8])>> {
+        if seq.len() < self.fixed_length() {
+            return None;
+        }
+        let mut offset = 0;
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let end = segment.length.map_or(seq.len(), |l| offset + l);
+                let slice = &seq[offset..end];
+                offset = end;
+                (segment.kind, slice)
+            })
+            .collect();
+        Some(segments)
+    }
+
+    /// Extracts the barcodes, UMIs and templates of a record or returns
+    /// `None` if it is too short
+    pub fn extract(&self, record: &FastqRecord) -> Option<ExtractedRead> {
+        let seqs = self.split(&record.seq)?;
+        let quals = self.split(&record.qual)?;
+        let mut extracted = ExtractedRead::default();
+        for ((kind, seq), (_, qual)) in seqs.into_iter().zip(quals) {
+            match kind {
+                SegmentKind::Barcode | SegmentKind::Cellular => extracted
+                    .barcodes
+                    .push(String::from_utf8_lossy(seq).into_owned()),
+                SegmentKind::Molecular => extracted
+                    .umis
+                    .push(String::from_utf8_lossy(seq).into_owned()),
+                SegmentKind::Template => extracted.templates.push((seq.to_vec(), qual.to_vec())),
+                SegmentKind::Skip => {}
+            }
+        }
+        Some(extracted)
+    }
+
+    /// Checks that there is one index per barcode segment
+    pub fn check_indices(&self, indices: usize) -> Result<()> {
+        check_indices(self, self.num_barcodes(), indices)
+    }
+
+    /// Extracts the segments of a record and assigns each barcode segment
+    /// against its index (the n-th barcode segment uses `indices[n]`).
+    /// Returns `None` if the record is too short and an error if the
+    /// number of indices differs from the number of barcode segments.
+    pub fn assign<T>(
+        &self,
+        record: &FastqRecord,
+        indices: &[&Disambiseq<T>],
+    ) -> Result<Option<StructuredRead>> {
+        self.check_indices(indices.len())?;
+        Ok(self
+            .extract(record)
+            .map(|extracted| assign_barcodes(extracted, indices)))
+    }
+}
+
+/// Checks that a structure with `barcodes` barcode segments is given one
+/// index per segment
+fn check_indices(structure: &impl fmt::Display, barcodes: usize, indices: usize) -> Result<()> {
+    if indices != barcodes {
+        return Err(Error::InvalidReadStructure {
+            structure: structure.to_string(),
+            message: format!("expected {barcodes} indices but found {indices}"),
+        });
+    }
+    Ok(())
+}
+
+/// Assigns each extracted barcode against its index
+fn assign_barcodes<T>(extracted: ExtractedRead, indices: &[&Disambiseq<T>]) -> StructuredRead {
+    let assignments = extracted
+        .barcodes
+        .iter()
+        .zip(indices)
+        .map(|(barcode, index)| index.assign(barcode))
+        .collect();
+    StructuredRead {
+        extracted,
+        assignments,
+    }
+}
+
+/// One read structure per mate (e.g. `"8B 16C12M +T"` for I1, R1 and
+/// R2) for barcodes and UMIs spread across several files
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct MultiReadStructure {
+    structures: Vec<ReadStructure>,
+}
+impl FromStr for MultiReadStructure {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let structures = s
+            .split_whitespace()
+            .map(|rs| rs.parse())
+            .collect::<Result<Vec<ReadStructure>>>()?;
+        if structures.is_empty() {
+            return Err(Error::InvalidReadStructure {
+                structure: s.to_string(),
+                message: "no mates".to_string(),
+            });
+        }
+        Ok(Self { structures })
+    }
+}
+impl fmt::Display for MultiReadStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, structure) in self.structures.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{structure}")?;
+        }
+        Ok(())
+    }
+}
+impl MultiReadStructure {
+    pub fn new(structures: Vec<ReadStructure>) -> Self {
+        Self { structures }
+    }
+
+    pub fn structures(&self) -> &[ReadStructure] {
+        &self.structures
+    }
+
+    /// Returns the number of barcode segments across all mates
+    pub fn num_barcodes(&self) -> usize {
+        self.structures.iter().map(|s| s.num_barcodes()).sum()
+    }
+
+    /// Checks that a read has one mate per structure
+    pub fn check_mates(&self, mates: usize) -> Result<()> {
+        if mates != self.structures.len() {
+            return Err(Error::InvalidReadStructure {
+                structure: self.to_string(),
+                message: format!("expected {} mates but found {mates}", self.structures.len()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that there is one index per barcode segment across all mates
+    pub fn check_indices(&self, indices: usize) -> Result<()> {
+        check_indices(self, self.num_barcodes(), indices)
+    }
+
+    /// Extracts the segments of every mate in order. Returns `None` if any
+    /// mate is too short and an error if the number of mates differs from
+    /// the number of structures.
+    pub fn extract<M: Borrow<FastqRecord>>(&self, mates: &[M]) -> Result<Option<ExtractedRead>> {
+        self.check_mates(mates.len())?;
+        let mut extracted = ExtractedRead::default();
+        for (structure, mate) in self.structures.iter().zip(mates) {
+            let Some(mate) = structure.extract(mate.borrow()) else {
+                return Ok(None);
+            };
+            extracted.barcodes.extend(mate.barcodes);
+            extracted.umis.extend(mate.umis);
+            extracted.templates.extend(mate.templates);
+        }
+        Ok(Some(extracted))
+    }
+
+    /// Extracts the segments of every mate and assigns each barcode
+    /// segment against its index (the n-th barcode segment across all
+    /// mates uses `indices[n]`). Returns `None` if any mate is too short
+    /// and an error if the number of mates or indices does not match the
+    /// structure.
+    pub fn assign<T, M: Borrow<FastqRecord>>(
+        &self,
+        mates: &[M],
+        indices: &[&Disambiseq<T>],
+    ) -> Result<Option<StructuredRead>> {
+        self.check_indices(indices.len())?;
+        Ok(self
+            .extract(mates)?
+            .map(|extracted| assign_barcodes(extracted, indices)))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn record(seq: &str) -> FastqRecord {
+        FastqRecord {
+            name: "r1".to_string(),
+            seq: seq.as_bytes().to_vec(),
+            qual: vec![b'I'; seq.len()],
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let rs: ReadStructure = "16B12M+T".parse().unwrap();
+        assert_eq!(
+            rs.segments(),
+            &[
+                Segment {
+                    kind: SegmentKind::Barcode,
+                    length: Some(16)
+                },
+                Segment {
+                    kind: SegmentKind::Molecular,
+                    length: Some(12)
+                },
+                Segment {
+                    kind: SegmentKind::Template,
+                    length: None
+                },
+            ]
+        );
+        assert_eq!(rs.fixed_length(), 28);
+        assert_eq!(rs.num_barcodes(), 1);
+        assert_eq!(rs.to_string(), "16B12M+T");
+
+        let rs: ReadStructure = "8B4S8B".parse().unwrap();
+        assert_eq!(rs.num_barcodes(), 2);
+        assert_eq!(rs.to_string(), "8B4S8B");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for structure in ["", "+T8B", "8X", "0B", "8", "B", "8B+"] {
+            assert!(
+                structure.parse::<ReadStructure>().is_err(),
+                "{structure} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn split() {
+        let rs: ReadStructure = "3B2S2M+T".parse().unwrap();
+        let segments = rs.split(b"ACTGGTTAAAA").unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                (SegmentKind::Barcode, &b"ACT"[..]),
+                (SegmentKind::Skip, &b"GG"[..]),
+                (SegmentKind::Molecular, &b"TT"[..]),
+                (SegmentKind::Template, &b"AAAA"[..]),
+            ]
+        );
+        assert!(rs.split(b"ACTGGT").is_none());
+        assert_eq!(rs.split(b"ACTGGTT").unwrap()[3].1, b"");
+    }
+
+    #[test]
+    fn extract() {
+        let rs: ReadStructure = "3B1S3B2M+T".parse().unwrap();
+        let extracted = rs.extract(&record("ACTNAGTCCGGG")).unwrap();
+        assert_eq!(extracted.barcodes, vec!["ACT", "AGT"]);
+        assert_eq!(extracted.umi(), "CC");
+        assert_eq!(
+            extracted.templates,
+            vec![(b"GGG".to_vec(), b"III".to_vec())]
+        );
+    }
+
+    #[test]
+    fn assign() {
+        let rs: ReadStructure = "3B1S3C".parse().unwrap();
+        let samples = Disambiseq::from_slice(&["ACT".to_string(), "AGT".to_string()]);
+        let cells = Disambiseq::from_slice(&["GGG".to_string()]);
+
+        let read = rs
+            .assign(&record("TCTNGGC"), &[&samples, &cells])
+            .unwrap()
+            .unwrap();
+        assert!(read.is_assigned());
+        assert_eq!(read.assignments[0].parent().unwrap().sequence(), "ACT");
+        assert_eq!(read.assignments[1].parent().unwrap().sequence(), "GGG");
+
+        let read = rs
+            .assign(&record("ATTNGGG"), &[&samples, &cells])
+            .unwrap()
+            .unwrap();
+        assert!(!read.is_assigned());
+        assert_eq!(read.assignments[0], Assignment::Ambiguous);
+
+        assert!(rs
+            .assign(&record("ACTNGG"), &[&samples, &cells])
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            rs.assign(&record("ACTNGGG"), &[&samples]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+    }
+
+    #[test]
+    fn multi() {
+        let rs: MultiReadStructure = "3B 3C2M+T".parse().unwrap();
+        assert_eq!(rs.structures().len(), 2);
+        assert_eq!(rs.num_barcodes(), 2);
+        assert_eq!(rs.to_string(), "3B 3C2M+T");
+        assert!("".parse::<MultiReadStructure>().is_err());
+        assert!("3B 3X".parse::<MultiReadStructure>().is_err());
+
+        let samples = Disambiseq::from_slice(&["ACT".to_string()]);
+        let cells = Disambiseq::from_slice(&["GGG".to_string()]);
+        let mates = [record("TCT"), record("GGCAAGATC")];
+        let read = rs.assign(&mates, &[&samples, &cells]).unwrap().unwrap();
+        assert!(read.is_assigned());
+        assert_eq!(read.extracted.umi(), "AA");
+        assert_eq!(read.extracted.templates[0].0, b"GATC");
+
+        assert!(rs
+            .assign(&[record("TCT"), record("GGCA")], &[&samples, &cells])
+            .unwrap()
+            .is_none());
+
+        assert!(rs.check_mates(2).is_ok());
+        assert!(matches!(
+            rs.check_mates(3),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+        assert!(matches!(
+            rs.extract(&[record("TCT")]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+        assert!(matches!(
+            rs.assign(&mates, &[&samples]),
+            Err(Error::InvalidReadStructure { .. })
+        ));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{error::Result, sequence::Sequence, Disambiseq, PairwiseDistances, SeqWrapper};
+use hashbrown::HashMap;
+use std::io::Write;
+
+/// How the one-mismatch neighbours of a single parent were indexed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParentReport {
+    pub parent: SeqWrapper,
+    /// The number of one-mismatch neighbours of the parent
+    pub neighbours: usize,
+    /// Neighbours that resolve to the parent
+    pub unambiguous: usize,
+    /// Neighbours shared with at least one other parent
+    pub ambiguous: usize,
+    /// Neighbours that are another parent or its reverse complement
+    pub shadowed: usize,
+}
+impl ParentReport {
+    /// Returns the fraction of neighbours that resolve to the parent
+    pub fn fraction(&self) -> f64 {
+        if self.neighbours == 0 {
+            1.0
+        } else {
+            self.unambiguous as f64 / self.neighbours as f64
+        }
+    }
+
+    /// Returns true if at least one neighbour does not resolve to the
+    /// parent
+    pub fn has_unresolved(&self) -> bool {
+        self.unambiguous < self.neighbours
+    }
+}
+
+/// A problem found while validating a library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LibraryIssue {
+    /// A parent was inserted more than once
+    Duplicate { parent: SeqWrapper, repeats: usize },
+    /// Two parents of equal length are fewer than 3 mismatches apart, so
+    /// some of their aliases cannot be corrected
+    TooClose {
+        a: SeqWrapper,
+        b: SeqWrapper,
+        distance: usize,
+    },
+    /// Some neighbours of a parent do not resolve to it
+    Unresolved {
+        parent: SeqWrapper,
+        unambiguous: usize,
+        neighbours: usize,
+    },
+}
+
+/// The correctability of a library
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryReport {
+    /// The minimum Hamming distance between parents of equal length.
+    /// Parents of different lengths never share an alias, so they are not
+    /// compared.
+    pub min_distance: Option<usize>,
+    /// A pair of parents at the minimum distance
+    pub closest: Option<(SeqWrapper, SeqWrapper)>,
+    /// Pairs of equal-length parents fewer than 3 mismatches apart with
+    /// their distance, sorted by sequence
+    pub too_close: Vec<(SeqWrapper, SeqWrapper, usize)>,
+    /// Parents inserted more than once with their number of repeats
+    pub duplicates: Vec<(SeqWrapper, usize)>,
+    /// The neighbourhood of every parent, sorted by sequence
+    pub parents: Vec<ParentReport>,
+}
+impl LibraryReport {
+    /// Returns the parents with neighbours that do not resolve to them
+    pub fn unresolved(&self) -> impl Iterator<Item = &ParentReport> {
+        self.parents.iter().filter(|p| p.has_unresolved())
+    }
+
+    /// Returns true if there are no duplicates and every neighbour of
+    /// every parent resolves to it
+    pub fn is_correctable(&self) -> bool {
+        self.duplicates.is_empty() && self.unresolved().next().is_none()
+    }
+
+    /// Writes the per-parent neighbourhoods as TSV
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "parent\tneighbours\tunambiguous\tambiguous\tshadowed\tfraction"
+        )?;
+        for p in &self.parents {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{:.4}",
+                p.parent.sequence(),
+                p.neighbours,
+                p.unambiguous,
+                p.ambiguous,
+                p.shadowed,
+                p.fraction()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Disambiseq<T> {
+    /// Returns the parents sorted by sequence
+    fn ordered_parents(&self) -> Vec<&SeqWrapper> {
+        let mut parents: Vec<&SeqWrapper> = self.parents().iter().collect();
+        parents.sort_unstable_by(|a, b| a.sequence().cmp(b.sequence()));
+        parents
+    }
+
+    /// Classifies the one-mismatch neighbours of a parent
+    fn parent_report(&self, parent: &SeqWrapper) -> ParentReport {
+        let neighbours = Sequence::new(parent.sequence()).mutate_all();
+        let mut report = ParentReport {
+            parent: parent.clone(),
+            neighbours: neighbours.len(),
+            unambiguous: 0,
+            ambiguous: 0,
+            shadowed: 0,
+        };
+        for neighbour in neighbours {
+            if self.get_parent(&neighbour) == Some(parent)
+                && !self.parents().contains(neighbour.as_str())
+            {
+                report.unambiguous += 1;
+            } else if self.candidates(&neighbour).is_some() {
+                report.ambiguous += 1;
+            } else {
+                report.shadowed += 1;
+            }
+        }
+        report
+    }
+
+    /// Returns the closest pair of equal-length parents and the pairs
+    /// fewer than 3 mismatches apart, comparing each length separately.
+    /// Close pairs are found by the blocked search of
+    /// [`PairwiseDistances::within`], and all pairs of a length are only
+    /// scanned when none are close.
+    #[allow(clippy::type_complexity)]
+    fn parent_distances<'p>(
+        parents: &[&'p SeqWrapper],
+    ) -> (
+        Option<(&'p SeqWrapper, &'p SeqWrapper, usize)>,
+        Vec<(SeqWrapper, SeqWrapper, usize)>,
+    ) {
+        let mut by_length: HashMap<usize, Vec<&SeqWrapper>> = HashMap::new();
+        for &parent in parents {
+            by_length
+                .entry(parent.sequence().len())
+                .or_default()
+                .push(parent);
+        }
+
+        let mut closest: Option<(&SeqWrapper, &SeqWrapper, usize)> = None;
+        let mut too_close = Vec::new();
+        for group in by_length.values() {
+            let sequences: Vec<&str> = group.iter().map(|p| p.sequence()).collect();
+            let distances = PairwiseDistances::new(&sequences);
+            let close = distances.within(2);
+            let nearest = match close.iter().min_by_key(|(_, _, d)| *d) {
+                Some(&pair) => Some(pair),
+                None => distances.closest(),
+            };
+            if let Some((i, j, d)) = nearest {
+                let better = closest
+                    .is_none_or(|(a, _, min)| (d, group[i].sequence()) < (min, a.sequence()));
+                if better {
+                    closest = Some((group[i], group[j], d));
+                }
+            }
+            too_close.extend(
+                close
+                    .into_iter()
+                    .map(|(i, j, d)| (group[i].clone(), group[j].clone(), d)),
+            );
+        }
+        too_close.sort_unstable_by(|a, b| {
+            (a.0.sequence(), a.1.sequence()).cmp(&(b.0.sequence(), b.1.sequence()))
+        });
+        (closest, too_close)
+    }
+
+    /// Reports how correctable the library is: the minimum distance
+    /// between parents, duplicate parents and the share of each parent's
+    /// neighbours that stayed unambiguous
+    pub fn report(&self) -> LibraryReport {
+        let parents = self.ordered_parents();
+        let (closest, too_close) = Self::parent_distances(&parents);
+        let mut duplicates: Vec<(SeqWrapper, usize)> = self
+            .duplicates()
+            .iter()
+            .map(|(p, n)| (p.clone(), *n))
+            .collect();
+        duplicates.sort_unstable_by(|a, b| a.0.sequence().cmp(b.0.sequence()));
+        LibraryReport {
+            min_distance: closest.map(|(_, _, d)| d),
+            closest: closest.map(|(a, b, _)| (a.clone(), b.clone())),
+            too_close,
+            duplicates,
+            parents: parents.into_iter().map(|p| self.parent_report(p)).collect(),
+        }
+    }
+
+    /// Lists every problem preventing the library from correcting all of
+    /// its one-mismatch aliases. An empty list means the library is fully
+    /// correctable.
+    pub fn validate(&self) -> Vec<LibraryIssue> {
+        let report = self.report();
+        let mut issues: Vec<LibraryIssue> = report
+            .duplicates
+            .iter()
+            .map(|(parent, repeats)| LibraryIssue::Duplicate {
+                parent: parent.clone(),
+                repeats: *repeats,
+            })
+            .collect();
+        issues.extend(
+            report
+                .too_close
+                .iter()
+                .map(|(a, b, distance)| LibraryIssue::TooClose {
+                    a: a.clone(),
+                    b: b.clone(),
+                    distance: *distance,
+                }),
+        );
+        issues.extend(report.unresolved().map(|p| LibraryIssue::Unresolved {
+            parent: p.parent.clone(),
+            unambiguous: p.unambiguous,
+            neighbours: p.neighbours,
+        }));
+        issues
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut dsq = Disambiseq::new();
+        for parent in ["AAAA", "AAAT", "GGGG", "AAAA", "CCCC"] {
+            dsq.insert(parent);
+        }
+        let report = dsq.report();
+        assert_eq!(report.min_distance, Some(1));
+        let (a, b) = report.closest.clone().unwrap();
+        assert_eq!((a.sequence(), b.sequence()), ("AAAA", "AAAT"));
+        assert_eq!(report.too_close.len(), 1);
+        assert_eq!(report.too_close[0].2, 1);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].1, 1);
+        assert!(!report.is_correctable());
+
+        // AAAA and AAAT share 2 ambiguous neighbours (AAAC, AAAG) and are
+        // each other's neighbour
+        let aaaa = &report.parents[0];
+        assert_eq!(aaaa.parent.sequence(), "AAAA");
+        assert_eq!(aaaa.neighbours, 12);
+        assert_eq!(aaaa.unambiguous, 9);
+        assert_eq!(aaaa.ambiguous, 2);
+        assert_eq!(aaaa.shadowed, 1);
+        assert!((aaaa.fraction() - 0.75).abs() < 1e-9);
+
+        let unresolved: Vec<&str> = report.unresolved().map(|p| p.parent.sequence()).collect();
+        assert_eq!(unresolved, vec!["AAAA", "AAAT"]);
+
+        let mut out = Vec::new();
+        report.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().nth(1), Some("AAAA\t12\t9\t2\t1\t0.7500"));
+        assert_eq!(out.lines().count(), 5);
+    }
+
+    #[test]
+    fn mixed_lengths() {
+        // parents of different lengths are never compared
+        let dsq = Disambiseq::from_slice(&["A".to_string(), "CC".to_string()]);
+        let report = dsq.report();
+        assert_eq!(report.min_distance, None);
+        assert!(report.too_close.is_empty());
+        assert!(!dsq
+            .validate()
+            .iter()
+            .any(|issue| matches!(issue, LibraryIssue::TooClose { .. })));
+
+        // the closest pair is taken across the lengths
+        let parents = ["AAAA", "AACC", "GGGGGG", "GGGGGT", "TTTTTT"];
+        let dsq = Disambiseq::from_slice(&parents.map(String::from));
+        let report = dsq.report();
+        assert_eq!(report.min_distance, Some(1));
+        let (a, b) = report.closest.unwrap();
+        assert_eq!((a.sequence(), b.sequence()), ("GGGGGG", "GGGGGT"));
+        let close: Vec<_> = report
+            .too_close
+            .iter()
+            .map(|(a, b, d)| (a.sequence(), b.sequence(), *d))
+            .collect();
+        assert_eq!(close, vec![("AAAA", "AACC", 2), ("GGGGGG", "GGGGGT", 1)]);
+    }
+
+    #[test]
+    fn all_ambiguous() {
+        // every neighbour of a parent can be shared without any resolving
+        let report = ParentReport {
+            parent: SeqWrapper(std::sync::Arc::new("A".to_string())),
+            neighbours: 3,
+            unambiguous: 0,
+            ambiguous: 3,
+            shadowed: 0,
+        };
+        assert!(report.has_unresolved());
+    }
+
+    #[test]
+    fn validate() {
+        let dsq = Disambiseq::from_slice(&["AAAA".to_string(), "CCCC".to_string()]);
+        assert!(dsq.validate().is_empty());
+        assert!(dsq.report().is_correctable());
+
+        let mut dsq = Disambiseq::from_slice(&["AAAA".to_string(), "AACC".to_string()]);
+        dsq.insert("AAAA");
+        let issues = dsq.validate();
+        assert_eq!(issues.len(), 4);
+        assert!(matches!(
+            &issues[0],
+            LibraryIssue::Duplicate { repeats: 1, .. }
+        ));
+        assert!(matches!(
+            &issues[1],
+            LibraryIssue::TooClose { distance: 2, .. }
+        ));
+        assert!(matches!(
+            &issues[2],
+            LibraryIssue::Unresolved { parent, .. } if parent.sequence() == "AAAA"
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    dual_index::DualIndex,
+    error::{Error, Result},
+    utils::{hamming_distance, validate_sequence},
+    Disambiseq,
+};
+use hashbrown::{HashMap, HashSet};
+use std::io::BufRead;
+
+/// A sample row of an Illumina `SampleSheet.csv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleSheetRow {
+    /// The lane of the sample or `None` if the sheet has no lane column
+    pub lane: Option<u32>,
+    pub sample_id: String,
+    /// The i7 index
+    pub index: String,
+    /// The i5 index if the run is dual-indexed
+    pub index2: Option<String>,
+}
+
+/// Two samples of the same lane whose indices cannot be told apart within
+/// the configured number of mismatches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexCollision {
+    pub lane: Option<u32>,
+    pub samples: (String, String),
+    /// Hamming distance between the (truncated) i7 indices
+    pub index_distance: usize,
+    /// Hamming distance between the (truncated) i5 indices
+    pub index2_distance: Option<usize>,
+    /// One-mismatch i7 aliases shared by both samples (the `ambiguous` set)
+    pub shared_aliases: Vec<String>,
+    /// One-mismatch i5 aliases shared by both samples
+    pub shared_aliases2: Vec<String>,
+}
+
+/// An Illumina `SampleSheet.csv` in either the v1 (`[Data]`) or v2
+/// (`[BCLConvert_Data]`) format
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleSheet {
+    rows: Vec<SampleSheetRow>,
+}
+impl SampleSheet {
+    /// Parses the data section of a sample sheet
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut in_data = false;
+        let mut found = false;
+        let mut columns: Option<Columns> = None;
+        let mut rows = Vec::new();
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let number = idx + 1;
+
+            if line.starts_with('[') {
+                let section = line.split(',').next().unwrap_or_default();
+                in_data = matches!(section, "[Data]" | "[BCLConvert_Data]");
+                found |= in_data;
+                columns = None;
+                continue;
+            }
+            if !in_data || line.split(',').all(|f| f.trim().is_empty()) {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let Some(cols) = &columns else {
+                columns = Some(Columns::from_header(&fields, number)?);
+                continue;
+            };
+            rows.push(cols.parse(&fields, number)?);
+        }
+
+        if !found {
+            return Err(Error::MissingSection {
+                section: "[Data] or [BCLConvert_Data]".to_string(),
+            });
+        }
+        Ok(Self { rows })
+    }
+
+    pub fn rows(&self) -> &[SampleSheetRow] {
+        &self.rows
+    }
+
+    /// Returns the distinct lanes of the sheet in order of appearance
+    pub fn lanes(&self) -> Vec<Option<u32>> {
+        let mut seen = HashSet::new();
+        self.rows
+            .iter()
+            .filter(|r| seen.insert(r.lane))
+            .map(|r| r.lane)
+            .collect()
+    }
+
+    /// Returns the rows of a lane
+    pub fn lane_rows(&self, lane: Option<u32>) -> impl Iterator<Item = &SampleSheetRow> {
+        self.rows.iter().filter(move |r| r.lane == lane)
+    }
+
+    /// Builds the i7 index library of a lane with sample ids as payloads
+    pub fn index_library(&self, lane: Option<u32>) -> Disambiseq<String> {
+        let mut dsq = Disambiseq::named();
+        self.lane_rows(lane)
+            .for_each(|r| dsq.insert_named(&r.index, r.sample_id.clone()));
+        dsq
+    }
+
+    /// Builds the dual-index library of a lane. Rows without an i5 index
+    /// are skipped.
+    pub fn dual_index_library(&self, lane: Option<u32>) -> DualIndex {
+        let mut dual = DualIndex::new();
+        self.lane_rows(lane).for_each(|r| {
+            if let Some(index2) = &r.index2 {
+                dual.add_sample(&r.sample_id, &r.index, index2);
+            }
+        });
+        dual
+    }
+
+    /// Lists every pair of samples within a lane whose indices are within
+    /// `2 * mismatches` of each other, so that a read with up to
+    /// `mismatches` errors per index could belong to either sample.
+    ///
+    /// Indices of differing lengths are compared over the shortest length
+    /// in the lane. For dual-indexed samples both indices must collide.
+    pub fn validate(&self, mismatches: usize) -> Vec<IndexCollision> {
+        let mut collisions = Vec::new();
+        for lane in self.lanes() {
+            let rows: Vec<&SampleSheetRow> = self.lane_rows(lane).collect();
+            let i7: Vec<&str> = rows.iter().map(|r| r.index.as_str()).collect();
+            let i5: Vec<Option<&str>> = rows.iter().map(|r| r.index2.as_deref()).collect();
+            let i7 = Truncated::new(&i7);
+            let i5 = Truncated::new(&i5.iter().flatten().copied().collect::<Vec<_>>());
+
+            for a in 0..rows.len() {
+                for b in (a + 1)..rows.len() {
+                    let (ra, rb) = (rows[a], rows[b]);
+                    let index_distance = i7.distance(&ra.index, &rb.index);
+                    if index_distance > 2 * mismatches {
+                        continue;
+                    }
+                    let index2_distance = match (&ra.index2, &rb.index2) {
+                        (Some(x), Some(y)) => Some(i5.distance(x, y)),
+                        _ => None,
+                    };
+                    if index2_distance.is_some_and(|d| d > 2 * mismatches) {
+                        continue;
+                    }
+                    collisions.push(IndexCollision {
+                        lane,
+                        samples: (ra.sample_id.clone(), rb.sample_id.clone()),
+                        index_distance,
+                        index2_distance,
+                        shared_aliases: i7.shared(&ra.index, &rb.index),
+                        shared_aliases2: match (&ra.index2, &rb.index2) {
+                            (Some(x), Some(y)) => i5.shared(x, y),
+                            _ => Vec::new(),
+                        },
+                    });
+                }
+            }
+        }
+        collisions
+    }
+}
+
+/// The indices of a lane truncated to their shortest length along with
+/// the one-mismatch library built from them
+struct Truncated {
+    length: usize,
+    shared: HashMap<(String, String), Vec<String>>,
+}
+impl Truncated {
+    fn new(indices: &[&str]) -> Self {
+        let length = indices.iter().map(|i| i.len()).min().unwrap_or(0);
+        let mut dsq = Disambiseq::new();
+        indices.iter().for_each(|i| dsq.insert(&i[..length]));
+
+        // collect the ambiguous aliases shared between each pair of parents
+        let mut shared: HashMap<(String, String), Vec<String>> = HashMap::new();
+        for alias in dsq.ambiguous() {
+            let candidates = dsq.candidates(alias.sequence()).unwrap_or_default();
+            for (i, a) in candidates.iter().enumerate() {
+                for b in &candidates[i + 1..] {
+                    shared
+                        .entry(Self::key(a.sequence(), b.sequence()))
+                        .or_default()
+                        .push(alias.sequence().to_string());
+                }
+            }
+        }
+        shared
+            .values_mut()
+            .for_each(|aliases| aliases.sort_unstable());
+        Self { length, shared }
+    }
+
+    fn key(a: &str, b: &str) -> (String, String) {
+        if a <= b {
+            (a.to_string(), b.to_string())
+        } else {
+            (b.to_string(), a.to_string())
+        }
+    }
+
+    fn distance(&self, a: &str, b: &str) -> usize {
+        hamming_distance(&a.as_bytes()[..self.length], &b.as_bytes()[..self.length]).unwrap_or(0)
+    }
+
+    fn shared(&self, a: &str, b: &str) -> Vec<String> {
+        self.shared
+            .get(&Self::key(&a[..self.length], &b[..self.length]))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Positions of the relevant columns of the data section
+struct Columns {
+    lane: Option<usize>,
+    sample_id: usize,
+    index: usize,
+    index2: Option<usize>,
+}
+impl Columns {
+    fn from_header(header: &[&str], line: usize) -> Result<Self> {
+        let find = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+        Ok(Self {
+            lane: find("Lane"),
+            sample_id: find("Sample_ID")
+                .ok_or_else(|| Error::malformed(line, "missing Sample_ID column"))?,
+            index: find("index").ok_or_else(|| Error::malformed(line, "missing index column"))?,
+            index2: find("index2"),
+        })
+    }
+
+    fn parse(&self, fields: &[&str], line: usize) -> Result<SampleSheetRow> {
+        let field = |idx: usize| fields.get(idx).copied().unwrap_or_default();
+
+        let lane = match self.lane.map(field) {
+            Some(lane) => Some(
+                lane.parse::<u32>()
+                    .map_err(|_| Error::malformed(line, format!("invalid lane '{lane}'")))?,
+            ),
+            None => None,
+        };
+
+        let sample_id = field(self.sample_id);
+        if sample_id.is_empty() {
+            return Err(Error::malformed(line, "missing Sample_ID"));
+        }
+
+        let index = field(self.index).to_ascii_uppercase();
+        validate_sequence(&index, line)?;
+
+        let index2 = match self.index2.map(field) {
+            Some(index2) if !index2.is_empty() => {
+                let index2 = index2.to_ascii_uppercase();
+                validate_sequence(&index2, line)?;
+                Some(index2)
+            }
+            _ => None,
+        };
+
+        Ok(SampleSheetRow {
+            lane,
+            sample_id: sample_id.to_string(),
+            index,
+            index2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    const V1: &str = "[Header]\n\
+                      IEMFileVersion,4\n\
+                      \n\
+                      [Reads]\n\
+                      151\n\
+                      \n\
+                      [Data]\n\
+                      Lane,Sample_ID,Sample_Name,index,index2\n\
+                      1,s1,,AAAAAAAA,CCCCCCCC\n\
+                      1,s2,,AAAAAAGG,CCCCCCGG\n\
+                      1,s3,,GGGGGGGG,TTTTTTTT\n\
+                      2,s4,,AAAAAAAA,CCCCCCCC\n\
+                      ,,,,\n";
+
+    const V2: &str = "[Header]\n\
+                      FileFormatVersion,2\n\
+                      [BCLConvert_Settings]\n\
+                      BarcodeMismatchesIndex1,1\n\
+                      [BCLConvert_Data]\n\
+                      Sample_ID,Index,Index2\n\
+                      s1,ACGTACGTAA,TTTT\n\
+                      s2,ACGTACGT,TTTA\n\
+                      s3,GGGGGGGG,CCCC\n";
+
+    #[test]
+    fn parse_v1() {
+        let sheet = SampleSheet::from_reader(V1.as_bytes()).unwrap();
+        assert_eq!(sheet.rows().len(), 4);
+        assert_eq!(sheet.lanes(), vec![Some(1), Some(2)]);
+        assert_eq!(
+            sheet.rows()[0],
+            SampleSheetRow {
+                lane: Some(1),
+                sample_id: "s1".to_string(),
+                index: "AAAAAAAA".to_string(),
+                index2: Some("CCCCCCCC".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_v2() {
+        let sheet = SampleSheet::from_reader(V2.as_bytes()).unwrap();
+        assert_eq!(sheet.rows().len(), 3);
+        assert_eq!(sheet.lanes(), vec![None]);
+        assert_eq!(sheet.rows()[1].index2.as_deref(), Some("TTTA"));
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let data = "[Data]\nSample_ID,index\ns1,ACGT\ns2,ACXT\n";
+        match SampleSheet::from_reader(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 4),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        let data = "[Data]\nSample_Name,index\ns1,ACGT\n";
+        match SampleSheet::from_reader(data.as_bytes()) {
+            Err(Error::Malformed { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(matches!(
+            SampleSheet::from_reader("[Header]\n".as_bytes()),
+            Err(Error::MissingSection { .. })
+        ));
+    }
+
+    #[test]
+    fn libraries() {
+        let sheet = SampleSheet::from_reader(V1.as_bytes()).unwrap();
+        let i7 = sheet.index_library(Some(1));
+        assert_eq!(i7.parents().len(), 3);
+        assert_eq!(i7.get_named("GGGGGGGA").unwrap(), "s3");
+        let dual = sheet.dual_index_library(Some(2));
+        assert_eq!(dual.len(), 1);
+        assert_eq!(dual.sample("AAAAAAAA", "CCCCCCCC"), Some("s4"));
+    }
+
+    #[test]
+    fn validate_lanes() {
+        let sheet = SampleSheet::from_reader(V1.as_bytes()).unwrap();
+        // s1 and s2 are two mismatches apart on both indices in lane 1, s4
+        // shares the indices of s1 but is in another lane
+        let collisions = sheet.validate(1);
+        assert_eq!(collisions.len(), 1);
+        let collision = &collisions[0];
+        assert_eq!(collision.lane, Some(1));
+        assert_eq!(collision.samples, ("s1".to_string(), "s2".to_string()));
+        assert_eq!(collision.index_distance, 2);
+        assert_eq!(collision.index2_distance, Some(2));
+        assert_eq!(collision.shared_aliases, vec!["AAAAAAAG", "AAAAAAGA"]);
+        assert_eq!(collision.shared_aliases2, vec!["CCCCCCCG", "CCCCCCGC"]);
+
+        assert!(sheet.validate(0).is_empty());
+    }
+
+    #[test]
+    fn validate_lengths() {
+        let sheet = SampleSheet::from_reader(V2.as_bytes()).unwrap();
+        // s1 and s2 are identical i7s over the shortest length
+        let collisions = sheet.validate(1);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].index_distance, 0);
+        assert_eq!(collisions[0].index2_distance, Some(1));
+        assert!(collisions[0].shared_aliases.is_empty());
+        assert_eq!(collisions[0].shared_aliases2, vec!["TTTC", "TTTG"]);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{fastq::extract, two_pass::Assignment, Disambiseq, SeqWrapper};
+use std::ops::{Bound, RangeBounds};
+
+/// The outcome of searching a read for a parent over a range of offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadMatch {
+    /// A single parent was found; `exact` is false if it was corrected
+    Found {
+        position: usize,
+        parent: SeqWrapper,
+        exact: bool,
+    },
+    /// Different offsets matched different parents equally well
+    Conflict(Vec<(usize, SeqWrapper)>),
+    /// No offset matched a single parent but some offset was ambiguous
+    Ambiguous,
+    /// No offset matched a parent
+    NotFound,
+}
+impl ReadMatch {
+    /// Returns the parent of a unique match
+    pub fn parent(&self) -> Option<&SeqWrapper> {
+        match self {
+            Self::Found { parent, .. } => Some(parent),
+            _ => None,
+        }
+    }
+
+    /// Returns the position of a unique match
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            Self::Found { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Disambiseq<T> {
+    /// Slides a window of each parent length over the offsets of a read
+    /// and returns the best matching (position, parent) pair.
+    ///
+    /// Exact matches take precedence over corrected ones. If several
+    /// offsets match the same parent equally well the earliest position is
+    /// returned, while matches of different parents are reported as a
+    /// conflict.
+    pub fn find_in_read<S, R>(&self, read: S, offset_range: R) -> ReadMatch
+    where
+        S: AsRef<[u8]>,
+        R: RangeBounds<usize>,
+    {
+        let read = read.as_ref();
+        let start = match offset_range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match offset_range.end_bound() {
+            Bound::Included(&e) => e.saturating_add(1),
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => usize::MAX,
+        };
+
+        let mut exact = Vec::new();
+        let mut corrected = Vec::new();
+        let mut ambiguous = false;
+        for position in start..end.min(read.len()) {
+            for &length in self.lengths() {
+                let Some(window) = extract(read, position, length) else {
+                    continue;
+                };
+                match self.assign(window) {
+                    Assignment::Exact(p) => exact.push((position, p)),
+                    Assignment::Corrected(p) => corrected.push((position, p)),
+                    Assignment::Ambiguous => ambiguous = true,
+                    _ => {}
+                }
+            }
+        }
+
+        let (is_exact, mut hits) = if exact.is_empty() {
+            (false, corrected)
+        } else {
+            (true, exact)
+        };
+        let Some((position, parent)) = hits.first().cloned() else {
+            return if ambiguous {
+                ReadMatch::Ambiguous
+            } else {
+                ReadMatch::NotFound
+            };
+        };
+        if hits.iter().all(|(_, p)| *p == parent) {
+            ReadMatch::Found {
+                position,
+                parent,
+                exact: is_exact,
+            }
+        } else {
+            // keep the first position of every conflicting parent
+            let mut seen = Vec::new();
+            hits.retain(|(_, p)| {
+                let new = !seen.contains(p);
+                if new {
+                    seen.push(p.clone());
+                }
+                new
+            });
+            ReadMatch::Conflict(hits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn library() -> Disambiseq {
+        Disambiseq::from_slice(&["ACTGACTG".to_string(), "GGGGCCCC".to_string()])
+    }
+
+    #[test]
+    fn staggered() {
+        let dsq = library();
+        for stagger in 0..=8 {
+            let read = format!("{}ACTGACTGTTTT", "N".repeat(stagger));
+            let hit = dsq.find_in_read(read, 0..=8);
+            assert_eq!(hit.position(), Some(stagger));
+            assert_eq!(hit.parent().unwrap().sequence(), "ACTGACTG");
+        }
+        assert_eq!(
+            dsq.find_in_read("NNNNNNNNNNACTGACTG", 0..=8),
+            ReadMatch::NotFound
+        );
+        assert_eq!(dsq.find_in_read("ACTG", ..), ReadMatch::NotFound);
+    }
+
+    #[test]
+    fn exact_over_corrected() {
+        let dsq = library();
+        // a corrected GGGGCCCC at 0 and an exact ACTGACTG at 9
+        let hit = dsq.find_in_read("GGGGCCCANACTGACTG", 0..=9);
+        assert_eq!(
+            hit,
+            ReadMatch::Found {
+                position: 9,
+                parent: dsq.get_parent("ACTGACTG").unwrap().clone(),
+                exact: true,
+            }
+        );
+
+        let hit = dsq.find_in_read("NGGGGCCCA", ..);
+        assert!(matches!(
+            hit,
+            ReadMatch::Found {
+                position: 1,
+                exact: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn conflict() {
+        let dsq = library();
+        let hit = dsq.find_in_read("ACTGACTGGGGGCCCC", ..);
+        match hit {
+            ReadMatch::Conflict(hits) => {
+                assert_eq!(hits.len(), 2);
+                assert_eq!(hits[0].0, 0);
+                assert_eq!(hits[0].1.sequence(), "ACTGACTG");
+                assert_eq!(hits[1].0, 8);
+                assert_eq!(hits[1].1.sequence(), "GGGGCCCC");
+            }
+            other => panic!("unexpected match: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn ambiguous() {
+        // AAAT is one mismatch from both parents
+        let dsq = Disambiseq::from_slice(&["AAAA".to_string(), "AATT".to_string()]);
+        assert_eq!(dsq.find_in_read("NAAAT", ..), ReadMatch::Ambiguous);
+        assert_eq!(
+            dsq.find_in_read("AAATAATT", ..)
+                .parent()
+                .unwrap()
+                .sequence(),
+            "AATT"
+        );
+        assert_eq!(dsq.find_in_read("NAAAT", 0..1), ReadMatch::NotFound);
+    }
+
+    #[test]
+    fn mixed_lengths() {
+        let dsq = Disambiseq::from_slice(&["ACTG".to_string(), "GGGGCCCC".to_string()]);
+        assert_eq!(dsq.lengths(), &[4, 8]);
+        for parent in ["ACTG", "GGGGCCCC"] {
+            let hit = dsq.find_in_read(format!("TT{parent}"), ..);
+            assert_eq!(hit.position(), Some(2));
+            assert_eq!(hit.parent().unwrap().sequence(), parent);
+        }
+    }
+
+    #[test]
+    fn unbounded_offsets() {
+        let dsq = library();
+        assert_eq!(
+            dsq.find_in_read("ACTGACTG", 0..=usize::MAX).position(),
+            Some(0)
+        );
+        assert_eq!(
+            dsq.find_in_read("ACTGACTG", (Bound::Excluded(usize::MAX), Bound::Unbounded)),
+            ReadMatch::NotFound
+        );
+    }
+}
@@ -15,6 +15,11 @@ impl<'a> Sequence<'a> {
         self.seq.len()
     }
 
+    /// Returns true if the internal sequence is empty
+    pub fn is_empty(&self) -> bool {
+        self.seq.is_empty()
+    }
+
     /// Creates the sequence from raw parts
     fn build_mutation(&self, prefix: &str, suffix: &str, insertion: &char) -> String {
         let mut sequence = String::with_capacity(self.len());
@@ -0,0 +1,241 @@
+use crate::{sequence::ByteSequence, utils::reverse_complement_bytes};
+use hashbrown::HashMap;
+use std::fmt;
+
+/// The strand on which a sequence was matched to its parent
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Strand {
+    /// The sequence matched the parent as given
+    Forward,
+    /// The sequence matched the reverse complement of the parent
+    Reverse,
+}
+
+impl Strand {
+    /// Returns the opposite strand
+    pub fn flip(self) -> Self {
+        match self {
+            Self::Forward => Self::Reverse,
+            Self::Reverse => Self::Forward,
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Forward => write!(f, "+"),
+            Self::Reverse => write!(f, "-"),
+        }
+    }
+}
+
+/// Which strands of a parent are indexed on insertion
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+pub enum StrandPolicy {
+    /// Index the parent and its point mutations
+    #[default]
+    Forward,
+    /// Index the reverse complement of the parent and its point mutations
+    Reverse,
+    /// Index both the parent and its reverse complement
+    Both,
+}
+impl StrandPolicy {
+    /// Returns true if the forward strand is indexed
+    pub fn forward(&self) -> bool {
+        matches!(self, Self::Forward | Self::Both)
+    }
+
+    /// Returns true if the reverse complement strand is indexed
+    pub fn reverse(&self) -> bool {
+        matches!(self, Self::Reverse | Self::Both)
+    }
+}
+
+/// A collision between the reverse complement strand of a parent and
+/// another (or the same) parent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrandCollision {
+    /// The parent is its own reverse complement
+    Palindrome { parent: Vec<u8> },
+    /// The reverse complement of `parent` is `other`
+    Exact { parent: Vec<u8>, other: Vec<u8> },
+    /// The reverse complement of `parent` is one mismatch from `other`
+    Mismatch {
+        parent: Vec<u8>,
+        other: Vec<u8>,
+        position: usize,
+    },
+}
+
+/// Lists every cross-strand collision within a set of parents.
+///
+/// These are the cases in which indexing the reverse complement strand
+/// (e.g. with `insert_with_reverse_complement`) overwrites or blacklists
+/// entries of another parent. Each colliding pair is reported once, in the
+/// order the parents are given, and a parent one mismatch from its own
+/// reverse complement is reported against itself.
+///
+/// # Panics
+///
+/// Panics if a parent contains a character other than `ACGTN`.
+pub fn strand_collisions<S: AsRef<[u8]>>(parents: &[S]) -> Vec<StrandCollision> {
+    let index: HashMap<&[u8], usize> = parents
+        .iter()
+        .enumerate()
+        .map(|(idx, p)| (p.as_ref(), idx))
+        .rev()
+        .collect();
+
+    let mut collisions = Vec::new();
+    for (idx, parent) in parents.iter().map(|p| p.as_ref()).enumerate() {
+        // skip duplicated parents
+        if index.get(parent) != Some(&idx) {
+            continue;
+        }
+
+        let revc = reverse_complement_bytes(parent);
+        if revc == parent {
+            collisions.push(StrandCollision::Palindrome {
+                parent: parent.to_vec(),
+            });
+            continue;
+        }
+
+        // collisions are symmetric so only report them against later parents
+        if let Some(&other) = index.get(revc.as_slice()) {
+            if other > idx {
+                collisions.push(StrandCollision::Exact {
+                    parent: parent.to_vec(),
+                    other: revc.clone(),
+                });
+            }
+        }
+
+        let sequence = ByteSequence::new(&revc);
+        for position in 0..sequence.len() {
+            for mutation in sequence.mutate_position(position) {
+                if let Some(&other) = index.get(mutation.as_slice()) {
+                    if other >= idx {
+                        collisions.push(StrandCollision::Mismatch {
+                            parent: parent.to_vec(),
+                            other: mutation,
+                            position,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn policy() {
+        assert!(StrandPolicy::Forward.forward());
+        assert!(!StrandPolicy::Forward.reverse());
+        assert!(!StrandPolicy::Reverse.forward());
+        assert!(StrandPolicy::Reverse.reverse());
+        assert!(StrandPolicy::Both.forward());
+        assert!(StrandPolicy::Both.reverse());
+    }
+
+    #[test]
+    fn collisions_none() {
+        let parents = vec![b"ACTAA".to_vec(), b"AGTAA".to_vec()];
+        assert!(strand_collisions(&parents).is_empty());
+    }
+
+    #[test]
+    fn collisions_palindrome() {
+        let parents = vec!["ACGT", "ACTAA"];
+        let collisions = strand_collisions(&parents);
+        assert_eq!(
+            collisions,
+            vec![StrandCollision::Palindrome {
+                parent: b"ACGT".to_vec()
+            }]
+        );
+    }
+
+    #[test]
+    fn collisions_exact() {
+        let parents = vec!["AACC", "ACTAA", "GGTT"];
+        let collisions = strand_collisions(&parents);
+        assert_eq!(
+            collisions,
+            vec![StrandCollision::Exact {
+                parent: b"AACC".to_vec(),
+                other: b"GGTT".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn collisions_mismatch() {
+        let parents = vec!["AACC", "GCTT", "GGTT"];
+        let collisions = strand_collisions(&parents);
+        assert_eq!(
+            collisions,
+            vec![
+                StrandCollision::Exact {
+                    parent: b"AACC".to_vec(),
+                    other: b"GGTT".to_vec(),
+                },
+                StrandCollision::Mismatch {
+                    parent: b"AACC".to_vec(),
+                    other: b"GCTT".to_vec(),
+                    position: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn collisions_mismatch_self() {
+        // the reverse complement of ACT is AGT
+        let collisions = strand_collisions(&["ACT"]);
+        assert_eq!(
+            collisions,
+            vec![StrandCollision::Mismatch {
+                parent: b"ACT".to_vec(),
+                other: b"ACT".to_vec(),
+                position: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn collisions_mismatch_ambiguous_base() {
+        // the reverse complement of ANCC is GGNT which has 4 variants at N
+        let collisions = strand_collisions(&["ANCC", "GGTT", "TGNT"]);
+        assert_eq!(
+            collisions,
+            vec![
+                StrandCollision::Mismatch {
+                    parent: b"ANCC".to_vec(),
+                    other: b"TGNT".to_vec(),
+                    position: 0,
+                },
+                StrandCollision::Mismatch {
+                    parent: b"ANCC".to_vec(),
+                    other: b"GGTT".to_vec(),
+                    position: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strand() {
+        assert_eq!(Strand::Forward.flip(), Strand::Reverse);
+        assert_eq!(Strand::Reverse.flip(), Strand::Forward);
+        assert_eq!(Strand::Forward.to_string(), "+");
+        assert_eq!(Strand::Reverse.to_string(), "-");
+    }
+}
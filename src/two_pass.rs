@@ -0,0 +1,271 @@
+use crate::{
+    disambiseq::SeqWrapper, prior::AbundancePrior, strand::Strand, utils::is_reverse_complement,
+    Disambiseq,
+};
+
+/// The outcome of correcting a single read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assignment {
+    /// The read matched a parent exactly
+    Exact(SeqWrapper),
+    /// The read was an unambiguous mismatch of a parent
+    Corrected(SeqWrapper),
+    /// The read was ambiguous and resolved using parent abundances
+    Rescued(SeqWrapper),
+    /// The read was ambiguous and could not be resolved
+    Ambiguous,
+    /// The read did not match any parent
+    Unassigned,
+}
+impl Assignment {
+    /// Returns the assigned parent if any
+    pub fn parent(&self) -> Option<&SeqWrapper> {
+        match self {
+            Self::Exact(p) | Self::Corrected(p) | Self::Rescued(p) => Some(p),
+            Self::Ambiguous | Self::Unassigned => None,
+        }
+    }
+}
+
+/// Counts of each assignment outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CorrectionSummary {
+    pub total: usize,
+    pub exact: usize,
+    pub corrected: usize,
+    pub rescued: usize,
+    pub ambiguous: usize,
+    pub unassigned: usize,
+}
+impl CorrectionSummary {
+    /// Records a single assignment
+    pub fn add(&mut self, assignment: &Assignment) {
+        self.total += 1;
+        match assignment {
+            Assignment::Exact(_) => self.exact += 1,
+            Assignment::Corrected(_) => self.corrected += 1,
+            Assignment::Rescued(_) => self.rescued += 1,
+            Assignment::Ambiguous => self.ambiguous += 1,
+            Assignment::Unassigned => self.unassigned += 1,
+        }
+    }
+
+    /// Returns the number of reads assigned to a parent
+    pub fn assigned(&self) -> usize {
+        self.exact + self.corrected + self.rescued
+    }
+}
+
+/// Corrects reads in two passes over the data.
+///
+/// The first pass counts exact parent matches and the second pass
+/// uses those counts to resolve mismatched and ambiguous reads. The prior
+/// and the summary accumulate over every call, so several inputs can be
+/// corrected against their combined abundances.
+#[derive(Debug, Clone)]
+pub struct TwoPassCorrector<'a, T = ()> {
+    index: &'a Disambiseq<T>,
+    prior: AbundancePrior,
+    summary: CorrectionSummary,
+}
+impl<'a, T> TwoPassCorrector<'a, T> {
+    pub fn new(index: &'a Disambiseq<T>) -> Self {
+        Self::with_prior(index, AbundancePrior::new())
+    }
+
+    /// Creates a corrector starting from an existing (possibly
+    /// preconfigured) prior
+    pub fn with_prior(index: &'a Disambiseq<T>, prior: AbundancePrior) -> Self {
+        Self {
+            index,
+            prior,
+            summary: CorrectionSummary::default(),
+        }
+    }
+
+    /// Returns the abundances observed so far
+    pub fn prior(&self) -> &AbundancePrior {
+        &self.prior
+    }
+
+    /// Returns the outcomes of the reads corrected so far
+    pub fn summary(&self) -> &CorrectionSummary {
+        &self.summary
+    }
+
+    /// First pass: counts the parent of the read if it matches the parent
+    /// or its reverse complement exactly
+    pub fn observe(&mut self, seq: &str) {
+        if let Some((parent, strand)) = self.index.get_parent_with_strand(seq) {
+            let exact = match strand {
+                Strand::Forward => parent.sequence() == seq,
+                Strand::Reverse => {
+                    is_reverse_complement(parent.sequence().as_bytes(), seq.as_bytes())
+                }
+            };
+            if exact {
+                self.prior.increment(parent.sequence());
+            }
+        }
+    }
+
+    /// Second pass: assigns a read to a parent using the observed
+    /// abundances. Qualities are phred+33 encoded.
+    pub fn assign(&self, seq: &str, qual: Option<&[u8]>) -> Assignment {
+        match self.index.assign(seq) {
+            Assignment::Ambiguous => {
+                match self.index.get_parent_with_prior(seq, qual, &self.prior) {
+                    Some(p) => Assignment::Rescued(p.clone()),
+                    None => Assignment::Ambiguous,
+                }
+            }
+            assignment => assignment,
+        }
+    }
+
+    /// Runs both passes over an iterator of reads with optional qualities
+    /// and lazily yields the per-read assignments. The exact matches are
+    /// added to the [`prior`](Self::prior) of earlier calls and each
+    /// yielded assignment to the running [`summary`](Self::summary).
+    ///
+    /// The iterator is cloned to be traversed twice, so it should be cheap
+    /// to restart (e.g. an iterator over a slice or a reopened file).
+    pub fn correct<I, S, Q>(&mut self, reads: I) -> Corrections<'_, 'a, T, I::IntoIter>
+    where
+        I: IntoIterator<Item = (S, Option<Q>)> + Clone,
+        S: AsRef<str>,
+        Q: AsRef<[u8]>,
+    {
+        reads
+            .clone()
+            .into_iter()
+            .for_each(|(seq, _)| self.observe(seq.as_ref()));
+        Corrections {
+            corrector: self,
+            reads: reads.into_iter(),
+        }
+    }
+}
+
+/// The second pass of [`TwoPassCorrector::correct`], yielding one
+/// assignment per read
+#[derive(Debug)]
+pub struct Corrections<'c, 'a, T, I> {
+    corrector: &'c mut TwoPassCorrector<'a, T>,
+    reads: I,
+}
+impl<T, I, S, Q> Iterator for Corrections<'_, '_, T, I>
+where
+    I: Iterator<Item = (S, Option<Q>)>,
+    S: AsRef<str>,
+    Q: AsRef<[u8]>,
+{
+    type Item = Assignment;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (seq, qual) = self.reads.next()?;
+        let assignment = self
+            .corrector
+            .assign(seq.as_ref(), qual.as_ref().map(|q| q.as_ref()));
+        self.corrector.summary.add(&assignment);
+        Some(assignment)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::strand::StrandPolicy;
+
+    fn index() -> Disambiseq {
+        Disambiseq::from_slice(&["ACT".to_string(), "AGT".to_string()])
+    }
+
+    #[test]
+    fn observe() {
+        let dsq = index();
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        tpc.observe("ACT");
+        tpc.observe("ACT");
+        tpc.observe("TCT");
+        assert_eq!(tpc.prior().count("ACT"), 2);
+        assert_eq!(tpc.prior().count("TCT"), 0);
+    }
+
+    #[test]
+    fn observe_reverse() {
+        let mut dsq = Disambiseq::new();
+        dsq.insert_stranded("AACT", StrandPolicy::Both);
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        tpc.observe("AACT");
+        tpc.observe("AGTT");
+        tpc.observe("AGTA");
+        assert_eq!(tpc.prior().count("AACT"), 2);
+
+        let mut dsq = Disambiseq::canonical();
+        dsq.insert("TTAGT");
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        tpc.observe("TTAGT");
+        tpc.observe("ACTAA");
+        tpc.observe("ACTAT");
+        assert_eq!(tpc.prior().count("TTAGT"), 2);
+    }
+
+    #[test]
+    fn correct_accumulates() {
+        let dsq = index();
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        let reads: Vec<(&str, Option<&[u8]>)> = vec![("ACT", None), ("TCT", None)];
+        assert_eq!(tpc.correct(reads.iter().copied()).count(), 2);
+        assert_eq!(tpc.correct(reads.iter().copied()).count(), 2);
+        assert_eq!(tpc.prior().count("ACT"), 2);
+        assert_eq!(tpc.summary().total, 4);
+        assert_eq!(tpc.summary().exact, 2);
+        assert_eq!(tpc.summary().corrected, 2);
+    }
+
+    #[test]
+    fn correct() {
+        let dsq = index();
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        let mut reads: Vec<(&str, Option<&[u8]>)> = vec![("ACT", None); 100];
+        reads.push(("AGT", None));
+        reads.push(("TCT", None));
+        reads.push(("ATT", None));
+        reads.push(("GGG", None));
+
+        let assignments: Vec<Assignment> = tpc.correct(reads.iter().copied()).collect();
+        let summary = *tpc.summary();
+        assert_eq!(assignments.len(), 104);
+        assert_eq!(assignments[100].parent().unwrap().sequence(), "AGT");
+        assert_eq!(
+            assignments[101],
+            Assignment::Corrected(dsq.get_parent("TCT").unwrap().clone())
+        );
+        assert_eq!(assignments[102].parent().unwrap().sequence(), "ACT");
+        assert_eq!(assignments[103], Assignment::Unassigned);
+        assert_eq!(summary.total, 104);
+        assert_eq!(summary.exact, 101);
+        assert_eq!(summary.corrected, 1);
+        assert_eq!(summary.rescued, 1);
+        assert_eq!(summary.unassigned, 1);
+        assert_eq!(summary.assigned(), 103);
+    }
+
+    #[test]
+    fn correct_ambiguous() {
+        let dsq = index();
+        let mut tpc = TwoPassCorrector::new(&dsq);
+        let reads = vec![
+            ("ACT", Some(b"III")),
+            ("AGT", Some(b"III")),
+            ("ATT", Some(b"III")),
+        ];
+        let mut assignments = tpc.correct(reads);
+        assert!(matches!(assignments.next(), Some(Assignment::Exact(_))));
+        assert!(matches!(assignments.next(), Some(Assignment::Exact(_))));
+        assert_eq!(assignments.next(), Some(Assignment::Ambiguous));
+        assert_eq!(assignments.next(), None);
+        assert_eq!(tpc.summary().ambiguous, 1);
+        assert_eq!(tpc.summary().total, 3);
+    }
+}
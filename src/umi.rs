@@ -0,0 +1,269 @@
+use crate::sequence::Sequence;
+use hashbrown::{HashMap, HashSet};
+use std::collections::VecDeque;
+
+/// The UMI-tools method used to group UMIs into molecules
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UmiMethod {
+    /// Every distinct UMI is its own molecule
+    Unique,
+    /// UMIs connected by one-mismatch edges form a single molecule
+    Cluster,
+    /// Each connected component is split into the fewest most abundant
+    /// UMIs whose neighbours cover the component
+    Adjacency,
+    /// UMI `a` absorbs its neighbour `b` if `count(a) >= 2 * count(b) - 1`
+    #[default]
+    Directional,
+}
+
+/// The molecules resolved from a set of UMIs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollapsedUmis {
+    /// The representative UMI of every observed UMI
+    pub corrected: HashMap<String, String>,
+    /// The number of reads of every representative UMI
+    pub counts: HashMap<String, u64>,
+}
+impl CollapsedUmis {
+    /// Returns the representative of an observed UMI
+    pub fn get(&self, umi: &str) -> Option<&str> {
+        self.corrected.get(umi).map(|u| u.as_str())
+    }
+
+    /// Returns the representatives of a sequence of observed UMIs
+    pub fn correct<S: AsRef<str>>(&self, umis: &[S]) -> Vec<Option<&str>> {
+        umis.iter().map(|u| self.get(u.as_ref())).collect()
+    }
+
+    /// Returns the number of deduplicated molecules
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns true if no UMIs were observed
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+/// Deduplicates the UMIs of a single cell or feature by grouping UMIs one
+/// mismatch apart, following the methods of UMI-tools.
+///
+/// Neighbours are found by generating every point mutation of a UMI (as
+/// the alias libraries do) and keeping the ones that were observed.
+#[derive(Debug, Clone, Default)]
+pub struct UmiCollapser {
+    method: UmiMethod,
+    counts: HashMap<String, u64>,
+}
+impl UmiCollapser {
+    pub fn new(method: UmiMethod) -> Self {
+        Self {
+            method,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Builds a collapser from the UMI of every read
+    pub fn from_umis<S: AsRef<str>>(method: UmiMethod, umis: &[S]) -> Self {
+        let mut collapser = Self::new(method);
+        umis.iter().for_each(|u| collapser.add(u.as_ref()));
+        collapser
+    }
+
+    /// Records a read with the given UMI
+    pub fn add(&mut self, umi: &str) {
+        self.add_count(umi, 1);
+    }
+
+    /// Records `count` reads with the given UMI
+    pub fn add_count(&mut self, umi: &str, count: u64) {
+        *self.counts.entry_ref(umi).or_default() += count;
+    }
+
+    /// Returns the number of reads observed for a UMI
+    pub fn count(&self, umi: &str) -> u64 {
+        self.counts.get(umi).copied().unwrap_or(0)
+    }
+
+    /// Returns the observed UMIs one mismatch away from a UMI
+    fn neighbours(&self, umi: &str) -> Vec<String> {
+        Sequence::new(umi)
+            .mutate_all()
+            .into_iter()
+            .filter(|m| self.counts.contains_key(m))
+            .collect()
+    }
+
+    /// Returns the observed UMIs by decreasing count, ties broken by
+    /// sequence
+    fn ranked(&self) -> Vec<&str> {
+        let mut umis: Vec<&str> = self.counts.keys().map(|u| u.as_str()).collect();
+        umis.sort_unstable_by(|a, b| self.count(b).cmp(&self.count(a)).then(a.cmp(b)));
+        umis
+    }
+
+    /// Returns the nodes reachable from `root` that are not yet grouped
+    fn traverse<F>(&self, root: &str, grouped: &HashSet<String>, edges: F) -> Vec<String>
+    where
+        F: Fn(&str) -> Vec<String>,
+    {
+        let mut component = vec![root.to_string()];
+        let mut seen: HashSet<String> = component.iter().cloned().collect();
+        let mut queue = VecDeque::from([root.to_string()]);
+        while let Some(node) = queue.pop_front() {
+            for next in edges(&node) {
+                if !grouped.contains(&next) && seen.insert(next.clone()) {
+                    component.push(next.clone());
+                    queue.push_back(next);
+                }
+            }
+        }
+        component
+    }
+
+    /// Splits a connected component with the adjacency method: the fewest
+    /// top-ranked UMIs whose neighbourhoods cover the component become
+    /// representatives, each absorbing its not yet absorbed neighbours
+    fn adjacency_groups(&self, component: &[String]) -> Vec<(String, Vec<String>)> {
+        let mut ranked: Vec<&str> = component.iter().map(|u| u.as_str()).collect();
+        ranked.sort_unstable_by(|a, b| self.count(b).cmp(&self.count(a)).then(a.cmp(b)));
+
+        let mut covered = HashSet::new();
+        let mut representatives = Vec::new();
+        for umi in ranked {
+            if covered.len() == component.len() {
+                break;
+            }
+            covered.insert(umi.to_string());
+            covered.extend(self.neighbours(umi));
+            representatives.push(umi);
+        }
+
+        let mut absorbed: HashSet<String> = representatives.iter().map(|u| u.to_string()).collect();
+        representatives
+            .iter()
+            .map(|rep| {
+                let mut group = vec![rep.to_string()];
+                for n in self.neighbours(rep) {
+                    if absorbed.insert(n.clone()) {
+                        group.push(n);
+                    }
+                }
+                (rep.to_string(), group)
+            })
+            .collect()
+    }
+
+    /// Groups the observed UMIs into molecules
+    pub fn collapse(&self) -> CollapsedUmis {
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        let mut grouped = HashSet::new();
+        for umi in self.ranked() {
+            if grouped.contains(umi) {
+                continue;
+            }
+            let new = match self.method {
+                UmiMethod::Unique => vec![(umi.to_string(), vec![umi.to_string()])],
+                UmiMethod::Cluster => {
+                    let component = self.traverse(umi, &grouped, |u| self.neighbours(u));
+                    vec![(umi.to_string(), component)]
+                }
+                UmiMethod::Adjacency => {
+                    let component = self.traverse(umi, &grouped, |u| self.neighbours(u));
+                    self.adjacency_groups(&component)
+                }
+                UmiMethod::Directional => {
+                    let component = self.traverse(umi, &grouped, |u| {
+                        // count(a) >= 2 * count(b) - 1 without underflow
+                        let count = self.count(u) + 1;
+                        self.neighbours(u)
+                            .into_iter()
+                            .filter(|n| count >= 2 * self.count(n))
+                            .collect()
+                    });
+                    vec![(umi.to_string(), component)]
+                }
+            };
+            new.iter().flat_map(|(_, group)| group).for_each(|u| {
+                grouped.insert(u.clone());
+            });
+            groups.extend(new);
+        }
+
+        let mut collapsed = CollapsedUmis::default();
+        for (rep, group) in groups {
+            let reads = group.iter().map(|u| self.count(u)).sum();
+            for umi in group {
+                collapsed.corrected.insert(umi, rep.clone());
+            }
+            collapsed.counts.insert(rep, reads);
+        }
+        collapsed
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    /// ACGT (10 reads) <- ACGA (5), ACGA <- TCGA (1), plus the isolated
+    /// GGGG (3) and its neighbour GGGC (3)
+    fn collapser(method: UmiMethod) -> UmiCollapser {
+        let mut collapser = UmiCollapser::new(method);
+        collapser.add_count("ACGT", 10);
+        collapser.add_count("ACGA", 5);
+        collapser.add_count("TCGA", 1);
+        collapser.add_count("GGGG", 3);
+        collapser.add_count("GGGC", 3);
+        collapser
+    }
+
+    #[test]
+    fn unique() {
+        let collapsed = collapser(UmiMethod::Unique).collapse();
+        assert_eq!(collapsed.len(), 5);
+        assert_eq!(collapsed.get("ACGA"), Some("ACGA"));
+    }
+
+    #[test]
+    fn cluster() {
+        let collapsed = collapser(UmiMethod::Cluster).collapse();
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed.get("TCGA"), Some("ACGT"));
+        assert_eq!(collapsed.counts["ACGT"], 16);
+        assert_eq!(collapsed.get("GGGG"), collapsed.get("GGGC"));
+    }
+
+    #[test]
+    fn adjacency() {
+        // ACGT does not cover TCGA so ACGA is needed as a second
+        // representative and absorbs it
+        let collapsed = collapser(UmiMethod::Adjacency).collapse();
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(collapsed.get("ACGA"), Some("ACGA"));
+        assert_eq!(collapsed.get("TCGA"), Some("ACGA"));
+        assert_eq!(collapsed.counts["ACGT"], 10);
+        assert_eq!(collapsed.counts["ACGA"], 6);
+    }
+
+    #[test]
+    fn directional() {
+        let collapsed = collapser(UmiMethod::Directional).collapse();
+        // ACGA (5) and TCGA (1) are absorbed by ACGT, while GGGG and GGGC
+        // have equal counts (3 < 2 * 3 - 1) and stay apart
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(collapsed.get("TCGA"), Some("ACGT"));
+        assert_eq!(collapsed.counts["ACGT"], 16);
+        assert_ne!(collapsed.get("GGGG"), collapsed.get("GGGC"));
+
+        let collapsed =
+            UmiCollapser::from_umis(UmiMethod::Directional, &["AAAA", "AAAA", "AAAT"]).collapse();
+        assert_eq!(
+            collapsed.correct(&["AAAT", "AAAA", "CCCC"]),
+            vec![Some("AAAA"), Some("AAAA"), None]
+        );
+        assert_eq!(collapsed.counts["AAAA"], 3);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::error::{Error, Result};
+use std::borrow::Cow;
+
 /// creates the reverse complement of a sequence
 pub fn reverse_complement(sequence: &str) -> String {
     sequence
@@ -30,9 +33,126 @@ pub fn reverse_complement_bytes(sequence: &[u8]) -> Vec<u8> {
         .collect()
 }
 
+/// creates the reverse complement of a sequence of bytes, or `None` if it
+/// contains characters other than `ACGTN`
+pub fn try_reverse_complement_bytes(sequence: &[u8]) -> Option<Vec<u8>> {
+    sequence
+        .iter()
+        .rev()
+        .map(|c| match c {
+            b'A' => Some(b'T'),
+            b'C' => Some(b'G'),
+            b'G' => Some(b'C'),
+            b'T' => Some(b'A'),
+            b'N' => Some(b'N'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// creates the canonical form of a sequence of bytes: the lexicographic
+/// minimum of itself and its reverse complement. Sequences containing
+/// characters other than `ACGTN` are returned unchanged.
+pub fn canonical_bytes(sequence: &[u8]) -> Cow<'_, [u8]> {
+    match try_reverse_complement_bytes(sequence) {
+        Some(revc) if revc.as_slice() < sequence => Cow::Owned(revc),
+        _ => Cow::Borrowed(sequence),
+    }
+}
+
+/// creates the canonical form of a sequence
+pub fn canonical(sequence: &str) -> Cow<'_, str> {
+    match canonical_bytes(sequence.as_bytes()) {
+        Cow::Borrowed(_) => Cow::Borrowed(sequence),
+        // the reverse complement only contains ascii nucleotides
+        Cow::Owned(revc) => Cow::Owned(String::from_utf8(revc).unwrap()),
+    }
+}
+
+/// returns true if `b` is the reverse complement of `a`
+pub fn is_reverse_complement(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter().rev()).all(|(x, y)| {
+            matches!(
+                (x, y),
+                (b'A', b'T') | (b'T', b'A') | (b'C', b'G') | (b'G', b'C') | (b'N', b'N')
+            )
+        })
+}
+
+/// returns true if a sequence is its own reverse complement
+pub fn is_palindrome(sequence: &[u8]) -> bool {
+    sequence.len().is_multiple_of(2)
+        && sequence.iter().zip(sequence.iter().rev()).all(|(a, b)| {
+            matches!(
+                (a, b),
+                (b'A', b'T') | (b'T', b'A') | (b'C', b'G') | (b'G', b'C')
+            )
+        })
+}
+
+/// returns the position of the single mismatch between two sequences of
+/// equal length, or `None` if they differ at any other number of positions
+pub fn mismatch_position(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let mut mismatches = a.iter().zip(b).enumerate().filter(|(_, (x, y))| x != y);
+    match (mismatches.next(), mismatches.next()) {
+        (Some((pos, _)), None) => Some(pos),
+        _ => None,
+    }
+}
+
+/// returns the number of mismatches between two sequences of equal length,
+/// or `None` if their lengths differ
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a.len() != b.len() {
+        return None;
+    }
+    Some(a.iter().zip(b).filter(|(x, y)| x != y).count())
+}
+
+/// returns the edit distance (substitutions, insertions and deletions)
+/// between two sequences
+pub fn levenshtein_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Checks that a sequence is non-empty and only contains nucleotides
+pub fn validate_sequence(seq: &str, line: usize) -> Result<()> {
+    if seq.is_empty() {
+        return Err(Error::malformed(line, "empty sequence"));
+    }
+    if let Some(c) = seq
+        .chars()
+        .find(|c| !matches!(c, 'A' | 'C' | 'G' | 'T' | 'N'))
+    {
+        return Err(Error::malformed(
+            line,
+            format!("unexpected character '{c}' in sequence '{seq}'"),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod testing {
-    use crate::utils::reverse_complement_bytes;
+    use crate::utils::{
+        canonical, canonical_bytes, hamming_distance, is_palindrome, is_reverse_complement,
+        levenshtein_distance, mismatch_position, reverse_complement_bytes,
+        try_reverse_complement_bytes,
+    };
 
     use super::reverse_complement;
 
@@ -77,4 +197,62 @@ mod testing {
         let seq = b"BBBB";
         reverse_complement_bytes(seq);
     }
+
+    #[test]
+    fn test_mismatch_position() {
+        assert_eq!(mismatch_position(b"ACGT", b"ACTT"), Some(2));
+        assert_eq!(mismatch_position(b"ACGT", b"ACGT"), None);
+        assert_eq!(mismatch_position(b"ACGT", b"TCTT"), None);
+        assert_eq!(mismatch_position(b"ACGT", b"ACG"), None);
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(hamming_distance(b"ACGT", b"ACGT"), Some(0));
+        assert_eq!(hamming_distance(b"ACGT", b"TCGA"), Some(2));
+        assert_eq!(hamming_distance(b"ACGT", b"ACG"), None);
+    }
+
+    #[test]
+    fn test_levenshtein_distance() {
+        assert_eq!(levenshtein_distance(b"ACGT", b"ACGT"), 0);
+        assert_eq!(levenshtein_distance(b"ACGT", b"TCGA"), 2);
+        assert_eq!(levenshtein_distance(b"ACGT", b"CGTA"), 2);
+        assert_eq!(levenshtein_distance(b"ACGT", b"ACG"), 1);
+        assert_eq!(levenshtein_distance(b"", b"ACG"), 3);
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(canonical("ACTT"), "AAGT");
+        assert_eq!(canonical("AAGT"), "AAGT");
+        assert_eq!(canonical("ACGT"), "ACGT");
+        assert_eq!(canonical("AcTT"), "AcTT");
+        assert_eq!(canonical_bytes(b"TTAGT").as_ref(), b"ACTAA");
+    }
+
+    #[test]
+    fn test_is_palindrome() {
+        assert!(is_palindrome(b"ACGT"));
+        assert!(is_palindrome(b"GAATTC"));
+        assert!(!is_palindrome(b"ACGA"));
+        assert!(!is_palindrome(b"ACNGT"));
+    }
+
+    #[test]
+    fn test_is_reverse_complement() {
+        assert!(is_reverse_complement(b"AACT", b"AGTT"));
+        assert!(is_reverse_complement(b"ANC", b"GNT"));
+        assert!(!is_reverse_complement(b"AACT", b"AACT"));
+        assert!(!is_reverse_complement(b"AACT", b"AGT"));
+    }
+
+    #[test]
+    fn test_try_reverse_complement_bytes() {
+        assert_eq!(
+            try_reverse_complement_bytes(b"ATNCG"),
+            Some(b"CGNAT".to_vec())
+        );
+        assert_eq!(try_reverse_complement_bytes(b"ATBCG"), None);
+    }
 }
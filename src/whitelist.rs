@@ -0,0 +1,194 @@
+use crate::Disambiseq;
+use hashbrown::HashMap;
+
+/// A point of the rank-abundance curve of observed barcodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurvePoint {
+    /// The 1-based rank of the barcode by decreasing count
+    pub rank: usize,
+    pub barcode: String,
+    pub count: u64,
+}
+
+/// A whitelist derived from the observed barcodes
+#[derive(Debug, Clone)]
+pub struct InferredWhitelist {
+    /// The barcodes at or above the knee as parents
+    pub whitelist: Disambiseq,
+    /// The count of the last barcode kept
+    pub threshold: u64,
+    /// The rank of the last barcode kept (i.e. the number of cells)
+    pub knee: usize,
+    /// The full rank-abundance curve for plotting
+    pub curve: Vec<CurvePoint>,
+}
+
+/// Infers a barcode whitelist from the reads, as `umi_tools whitelist`
+/// does, by calling the barcodes above the knee of the rank-abundance
+/// curve.
+///
+/// The knee is the point of the log-log curve furthest above the line
+/// joining its first and last points, which is where the plateau of real
+/// cells falls off into the background.
+#[derive(Debug, Clone, Default)]
+pub struct WhitelistBuilder {
+    counts: HashMap<String, u64>,
+    expected_cells: Option<usize>,
+    min_count: u64,
+}
+impl WhitelistBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a read with the given barcode
+    pub fn add(&mut self, barcode: &str) {
+        self.add_count(barcode, 1);
+    }
+
+    /// Records `count` reads with the given barcode
+    pub fn add_count(&mut self, barcode: &str, count: u64) {
+        *self.counts.entry_ref(barcode).or_default() += count;
+    }
+
+    /// Keeps the top `n` barcodes instead of detecting the knee
+    pub fn with_expected_cells(mut self, n: usize) -> Self {
+        self.expected_cells = Some(n);
+        self
+    }
+
+    /// Ignores barcodes observed fewer than `min_count` times
+    pub fn with_min_count(mut self, min_count: u64) -> Self {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Returns the rank-abundance curve ordered by decreasing count, ties
+    /// broken by barcode
+    pub fn curve(&self) -> Vec<CurvePoint> {
+        let mut counts: Vec<(&String, &u64)> = self
+            .counts
+            .iter()
+            .filter(|(_, c)| **c >= self.min_count)
+            .collect();
+        counts.sort_unstable_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (barcode, count))| CurvePoint {
+                rank: idx + 1,
+                barcode: barcode.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    /// Returns the rank of the knee of a rank-abundance curve
+    fn knee(curve: &[CurvePoint]) -> usize {
+        if curve.len() < 3 {
+            return curve.len();
+        }
+        let points: Vec<(f64, f64)> = curve
+            .iter()
+            .map(|p| ((p.rank as f64).log10(), (p.count.max(1) as f64).log10()))
+            .collect();
+        let (x0, y0) = points[0];
+        let (x1, y1) = points[points.len() - 1];
+        let (dx, dy) = (x1 - x0, y1 - y0);
+
+        let mut best = (0, f64::MIN);
+        for (idx, (x, y)) in points.iter().enumerate() {
+            // signed distance above the chord up to a constant factor
+            let distance = dx * (y - y0) - dy * (x - x0);
+            if distance > best.1 {
+                best = (idx, distance);
+            }
+        }
+        best.0 + 1
+    }
+
+    /// Calls the whitelist and builds the index correcting the remaining
+    /// barcodes against it
+    pub fn build(&self) -> InferredWhitelist {
+        let curve = self.curve();
+        let knee = self
+            .expected_cells
+            .unwrap_or_else(|| Self::knee(&curve))
+            .min(curve.len());
+        let mut whitelist = Disambiseq::new();
+        curve[..knee]
+            .iter()
+            .for_each(|p| whitelist.insert(&p.barcode));
+        InferredWhitelist {
+            whitelist,
+            threshold: knee.checked_sub(1).map_or(0, |k| curve[k].count),
+            knee,
+            curve,
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    /// Encodes an integer as a barcode of eight bases
+    fn barcode(mut n: usize) -> String {
+        (0..8)
+            .map(|_| {
+                let base = ['A', 'C', 'G', 'T'][n % 4];
+                n /= 4;
+                base
+            })
+            .collect()
+    }
+
+    fn builder() -> WhitelistBuilder {
+        let mut builder = WhitelistBuilder::new();
+        for i in 0..50 {
+            builder.add_count(&barcode(i * 97), 1000 - 5 * i as u64);
+        }
+        for i in 0..500 {
+            builder.add_count(&barcode(10_000 + i * 31), (i % 5) as u64 + 1);
+        }
+        builder
+    }
+
+    #[test]
+    fn curve() {
+        let curve = builder().curve();
+        assert_eq!(curve.len(), 550);
+        assert_eq!(curve[0].rank, 1);
+        assert_eq!(curve[0].count, 1000);
+        assert!(curve.windows(2).all(|w| w[0].count >= w[1].count));
+        assert_eq!(builder().with_min_count(2).curve().len(), 450);
+    }
+
+    #[test]
+    fn knee() {
+        let inferred = builder().build();
+        assert_eq!(inferred.knee, 50);
+        assert_eq!(inferred.threshold, 755);
+        assert_eq!(inferred.whitelist.parents().len(), 50);
+
+        // reads of a cell with a sequencing error are corrected
+        let mut error = barcode(0);
+        error.replace_range(0..1, "C");
+        assert_eq!(
+            inferred.whitelist.get_parent(&error).unwrap().sequence(),
+            barcode(0)
+        );
+    }
+
+    #[test]
+    fn expected_cells() {
+        let inferred = builder().with_expected_cells(10).build();
+        assert_eq!(inferred.knee, 10);
+        assert_eq!(inferred.threshold, 955);
+        assert_eq!(inferred.whitelist.parents().len(), 10);
+
+        let inferred = WhitelistBuilder::new().build();
+        assert_eq!(inferred.knee, 0);
+        assert_eq!(inferred.threshold, 0);
+    }
+}